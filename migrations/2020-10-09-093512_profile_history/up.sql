@@ -0,0 +1,8 @@
+CREATE TABLE profile_history (
+    uuid UUID NOT NULL,
+    version INTEGER NOT NULL,
+    replaced_by VARCHAR[] NOT NULL,
+    replaced_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    profile JSONB NOT NULL,
+    PRIMARY KEY (uuid, version)
+);
@@ -0,0 +1,19 @@
+CREATE TYPE operation_type AS ENUM ('create', 'update');
+
+CREATE TABLE profile_changes (
+    id BIGSERIAL PRIMARY KEY,
+    uuid UUID NOT NULL,
+    version INTEGER NOT NULL,
+    field VARCHAR NOT NULL,
+    operation operation_type NOT NULL,
+    publisher VARCHAR NOT NULL,
+    old_value JSONB NOT NULL,
+    new_value JSONB NOT NULL,
+    old_display trust_type,
+    new_display trust_type,
+    changed_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX profile_changes_uuid_idx ON profile_changes (uuid, changed_at);
+CREATE INDEX profile_changes_field_idx ON profile_changes (field, changed_at);
+CREATE INDEX profile_changes_publisher_idx ON profile_changes (publisher, changed_at);
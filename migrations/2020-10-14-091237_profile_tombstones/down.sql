@@ -0,0 +1,2 @@
+DROP TABLE profile_tombstones;
+DROP TYPE deletion_type;
@@ -0,0 +1,10 @@
+CREATE TYPE deletion_type AS ENUM ('soft', 'hard');
+
+CREATE TABLE profile_tombstones (
+    uuid UUID NOT NULL,
+    version INTEGER NOT NULL,
+    deletion deletion_type NOT NULL,
+    publisher VARCHAR NOT NULL,
+    deleted_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (uuid, version, deletion)
+);
@@ -0,0 +1,2 @@
+DROP TABLE profile_events;
+DROP TYPE event_type;
@@ -0,0 +1,11 @@
+CREATE TYPE event_type AS ENUM ('create', 'update', 'erase');
+
+CREATE TABLE profile_events (
+    id BIGSERIAL PRIMARY KEY,
+    uuid UUID NOT NULL,
+    version INTEGER NOT NULL,
+    event event_type NOT NULL,
+    fields VARCHAR[] NOT NULL,
+    publishers VARCHAR[] NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,3 @@
+DROP TABLE webhook_deliveries;
+DROP TYPE delivery_status;
+DROP TABLE webhooks;
@@ -0,0 +1,22 @@
+CREATE TABLE profile_groups (
+    uuid UUID NOT NULL,
+    provider VARCHAR NOT NULL,
+    group_name VARCHAR NOT NULL,
+    display trust_type,
+    PRIMARY KEY (provider, group_name, uuid)
+);
+
+CREATE INDEX profile_groups_uuid_idx ON profile_groups (uuid);
+
+INSERT INTO profile_groups (uuid, provider, group_name, display)
+SELECT
+    p.uuid,
+    a.key,
+    g.key,
+    CASE WHEN a.value->'metadata'->>'display' IN ('public', 'authenticated', 'vouched', 'ndaed', 'staff')
+        THEN (a.value->'metadata'->>'display')::trust_type
+    END
+FROM profiles p,
+    jsonb_each(p.profile->'access_information') a,
+    jsonb_each(CASE WHEN jsonb_typeof(a.value->'values') = 'object'
+        THEN a.value->'values' ELSE '{}'::jsonb END) g;
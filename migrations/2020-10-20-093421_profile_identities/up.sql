@@ -0,0 +1,22 @@
+CREATE TABLE profile_identities (
+    uuid UUID NOT NULL,
+    identity_type VARCHAR NOT NULL,
+    value VARCHAR NOT NULL,
+    display trust_type,
+    PRIMARY KEY (identity_type, value, uuid)
+);
+
+CREATE INDEX profile_identities_uuid_idx ON profile_identities (uuid);
+
+INSERT INTO profile_identities (uuid, identity_type, value, display)
+SELECT
+    p.uuid,
+    i.key,
+    i.value->>'value',
+    CASE WHEN i.value->'metadata'->>'display' IN ('public', 'authenticated', 'vouched', 'ndaed', 'staff')
+        THEN (i.value->'metadata'->>'display')::trust_type
+    END
+FROM profiles p,
+    jsonb_each(p.profile->'identities') i
+WHERE jsonb_typeof(i.value->'value') = 'string'
+    AND i.value->>'value' <> '';
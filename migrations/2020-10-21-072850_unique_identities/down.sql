@@ -0,0 +1,2 @@
+ALTER TABLE profile_identities DROP CONSTRAINT profile_identities_pkey;
+ALTER TABLE profile_identities ADD PRIMARY KEY (identity_type, value, uuid);
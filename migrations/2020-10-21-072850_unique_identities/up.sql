@@ -0,0 +1,5 @@
+-- Empty values link nothing and would collide across profiles.
+DELETE FROM profile_identities WHERE value = '';
+-- Fails if two profiles already share an identity. Resolve those first.
+ALTER TABLE profile_identities DROP CONSTRAINT profile_identities_pkey;
+ALTER TABLE profile_identities ADD PRIMARY KEY (identity_type, value);
@@ -0,0 +1,8 @@
+ALTER TABLE profiles ADD COLUMN last_modified TIMESTAMP;
+
+UPDATE profiles
+SET last_modified = (profile->'last_modified'->'metadata'->>'last_modified')::timestamptz AT TIME ZONE 'UTC';
+
+ALTER TABLE profiles ALTER COLUMN last_modified SET NOT NULL;
+
+CREATE INDEX profiles_last_modified_idx ON profiles (last_modified);
@@ -1,11 +1,95 @@
+use crate::db::types::DeletionType;
+use crate::db::Pool;
+use crate::error::ApiError;
+use crate::profile::change::change_profile;
+use crate::profile::change::change_profiles;
+use crate::profile::cis_attributes::IdentifierGenerator;
+use crate::profile::delete::delete_profile;
+use crate::profile::publishers::PublisherRulesStore;
 use actix_web::dev::HttpServiceFactory;
 use actix_web::web;
 use actix_web::HttpResponse;
+use cis_profile::crypto::SecretStore;
+use cis_profile::schema::Profile;
+use serde::Deserialize;
+use uuid::Uuid;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
+const BATCH_LIMIT: usize = 64 * 1024 * 1024;
 
-fn change_user() -> HttpResponse {
-    HttpResponse::Ok().finish()
+#[derive(Deserialize)]
+pub struct DryRunQuery {
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteQuery {
+    #[serde(default)]
+    mode: DeletionType,
+    version: Option<i32>,
+}
+
+async fn change_user(
+    pool: web::Data<Pool>,
+    secret_store: web::Data<SecretStore>,
+    rules: web::Data<PublisherRulesStore>,
+    ids: web::Data<IdentifierGenerator>,
+    profile: web::Json<Profile>,
+    query: web::Query<DryRunQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let status = change_profile(
+        &pool,
+        &secret_store,
+        &rules,
+        &ids,
+        profile.into_inner(),
+        query.dry_run,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(status))
+}
+
+async fn change_users(
+    pool: web::Data<Pool>,
+    secret_store: web::Data<SecretStore>,
+    rules: web::Data<PublisherRulesStore>,
+    ids: web::Data<IdentifierGenerator>,
+    profiles: web::Json<Vec<Profile>>,
+    query: web::Query<DryRunQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let results = change_profiles(
+        &pool,
+        &secret_store,
+        &rules,
+        &ids,
+        profiles.into_inner(),
+        query.dry_run,
+    )
+    .await;
+    Ok(HttpResponse::Ok().json(results))
+}
+
+async fn delete_user(
+    pool: web::Data<Pool>,
+    secret_store: web::Data<SecretStore>,
+    ids: web::Data<IdentifierGenerator>,
+    uuid: web::Path<Uuid>,
+    profile: web::Json<Profile>,
+    query: web::Query<DeleteQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let query = query.into_inner();
+    let tombstone = delete_profile(
+        &pool,
+        &secret_store,
+        &ids,
+        uuid.into_inner(),
+        profile.into_inner(),
+        query.mode,
+        query.version,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(tombstone))
 }
 
 fn index() -> HttpResponse {
@@ -19,6 +103,12 @@ fn version() -> HttpResponse {
 pub fn change_app() -> impl HttpServiceFactory {
     web::scope("/change/v2")
         .service(web::resource("/user").route(web::post().to(change_user)))
+        .service(web::resource("/user/{uuid}").route(web::delete().to(delete_user)))
+        .service(
+            web::resource("/users")
+                .app_data(web::JsonConfig::default().limit(BATCH_LIMIT))
+                .route(web::post().to(change_users)),
+        )
         .service(web::resource("/version").to(version))
         .service(web::resource("").to(index))
 }
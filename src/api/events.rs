@@ -0,0 +1,61 @@
+use crate::db::retrieve::retrieve_events;
+use crate::db::retrieve::EventsQuery;
+use crate::db::Pool;
+use crate::error::ApiError;
+use crate::notify::Broadcaster;
+use crate::notify::StreamFilter;
+use actix_web::dev::HttpServiceFactory;
+use actix_web::http::header::CACHE_CONTROL;
+use actix_web::web;
+use actix_web::HttpResponse;
+use dino_park_gate::scope::ScopeAndUser;
+use dino_park_trust::Trust;
+use failure::Error;
+use futures::StreamExt;
+use serde_json::json;
+
+fn check_staff(scope_and_user: &ScopeAndUser) -> Result<(), ApiError> {
+    if scope_and_user.scope != Trust::Staff {
+        return Err(ApiError::Forbidden);
+    }
+    Ok(())
+}
+
+async fn events(
+    pool: web::Data<Pool>,
+    query: web::Query<EventsQuery>,
+    scope_and_user: ScopeAndUser,
+) -> Result<HttpResponse, ApiError> {
+    check_staff(&scope_and_user)?;
+    let query = query.into_inner();
+    let after = query.after.unwrap_or_default();
+    let connection = pool.get().map_err(Error::from)?;
+    let events = retrieve_events(&connection, query)?;
+    let next = events.last().map(|e| e.id).unwrap_or(after);
+    Ok(HttpResponse::Ok().json(json!({ "events": events, "next": next })))
+}
+
+async fn stream(
+    broadcaster: web::Data<Broadcaster>,
+    filter: web::Query<StreamFilter>,
+    scope_and_user: ScopeAndUser,
+) -> Result<HttpResponse, ApiError> {
+    check_staff(&scope_and_user)?;
+    let events = broadcaster.subscribe(filter.into_inner());
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .header(CACHE_CONTROL, "no-cache")
+        .streaming(events.map(Ok::<_, actix_web::Error>)))
+}
+
+/// The event feed at `/change/v2/events`. Unlike the rest of the change
+/// scope it needs a user token, so it is mounted in its own scope before
+/// `change_app` to be wrapped with the scope middleware.
+pub fn events_app() -> impl HttpServiceFactory {
+    web::resource("").route(web::get().to(events))
+}
+
+/// The event stream at `/change/v2/stream`, mounted like [`events_app`].
+pub fn stream_app() -> impl HttpServiceFactory {
+    web::resource("").route(web::get().to(stream))
+}
@@ -1,2 +1,4 @@
 pub mod change;
+pub mod events;
 pub mod person;
+pub mod webhooks;
@@ -1,11 +1,293 @@
+use crate::db::model::ProfileEntry;
+use crate::db::retrieve::retrieve_changes;
+use crate::db::retrieve::retrieve_group_members;
+use crate::db::retrieve::retrieve_groups;
+use crate::db::retrieve::retrieve_history;
+use crate::db::retrieve::retrieve_history_profile;
+use crate::db::retrieve::retrieve_profile;
+use crate::db::retrieve::retrieve_profile_by_identity;
+use crate::db::retrieve::retrieve_profile_by_primary_email;
+use crate::db::retrieve::retrieve_profile_by_primary_username;
+use crate::db::retrieve::retrieve_profile_by_user_id;
+use crate::db::retrieve::retrieve_profiles;
+use crate::db::retrieve::AuditFilter;
+use crate::db::retrieve::MembersQuery;
+use crate::db::retrieve::UsersQuery;
+use crate::db::types::TrustType;
+use crate::db::Pool;
+use crate::error::ApiError;
+use crate::error::DBError;
+use crate::profile::display::scrub_change;
+use crate::profile::display::scrub_profile;
+use crate::profile::display::DisplayFilter;
 use actix_web::dev::HttpServiceFactory;
 use actix_web::web;
+use actix_web::web::Bytes;
 use actix_web::HttpResponse;
+use cis_profile::schema::Profile;
+use diesel::pg::PgConnection;
+use dino_park_gate::scope::ScopeAndUser;
+use dino_park_trust::Trust;
+use failure::Error;
+use futures::stream;
+use serde::Deserialize;
+use serde_json::json;
+use uuid::Uuid;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
-fn change_user() -> HttpResponse {
-    HttpResponse::Ok().finish()
+#[derive(Deserialize)]
+pub struct ActiveQuery {
+    #[serde(default)]
+    active: DisplayFilter,
+}
+
+async fn user_by_uuid(
+    pool: web::Data<Pool>,
+    uuid: web::Path<Uuid>,
+    query: web::Query<ActiveQuery>,
+    scope_and_user: ScopeAndUser,
+) -> Result<HttpResponse, ApiError> {
+    let connection = pool.get().map_err(Error::from)?;
+    let profile = retrieve_profile(&connection, uuid.into_inner(), query.into_inner().active)?;
+    let profile = scrub_profile(profile, &TrustType::from(scope_and_user.scope));
+    Ok(HttpResponse::Ok().json(profile))
+}
+
+async fn user_by_user_id(
+    pool: web::Data<Pool>,
+    user_id: web::Path<String>,
+    query: web::Query<ActiveQuery>,
+    scope_and_user: ScopeAndUser,
+) -> Result<HttpResponse, ApiError> {
+    let connection = pool.get().map_err(Error::from)?;
+    let profile = retrieve_profile_by_user_id(&connection, &user_id, query.into_inner().active)?;
+    let profile = scrub_profile(profile, &TrustType::from(scope_and_user.scope));
+    Ok(HttpResponse::Ok().json(profile))
+}
+
+async fn user_by_primary_email(
+    pool: web::Data<Pool>,
+    primary_email: web::Path<String>,
+    query: web::Query<ActiveQuery>,
+    scope_and_user: ScopeAndUser,
+) -> Result<HttpResponse, ApiError> {
+    let connection = pool.get().map_err(Error::from)?;
+    let profile =
+        retrieve_profile_by_primary_email(&connection, &primary_email, query.into_inner().active)?;
+    let profile = scrub_profile(profile, &TrustType::from(scope_and_user.scope));
+    Ok(HttpResponse::Ok().json(profile))
+}
+
+async fn user_by_primary_username(
+    pool: web::Data<Pool>,
+    primary_username: web::Path<String>,
+    query: web::Query<ActiveQuery>,
+    scope_and_user: ScopeAndUser,
+) -> Result<HttpResponse, ApiError> {
+    let connection = pool.get().map_err(Error::from)?;
+    let profile = retrieve_profile_by_primary_username(
+        &connection,
+        &primary_username,
+        query.into_inner().active,
+    )?;
+    let profile = scrub_profile(profile, &TrustType::from(scope_and_user.scope));
+    Ok(HttpResponse::Ok().json(profile))
+}
+
+async fn user_by_identity(
+    pool: web::Data<Pool>,
+    path: web::Path<(String, String)>,
+    query: web::Query<ActiveQuery>,
+    scope_and_user: ScopeAndUser,
+) -> Result<HttpResponse, ApiError> {
+    let (identity_type, value) = path.into_inner();
+    let scope = TrustType::from(scope_and_user.scope);
+    let connection = pool.get().map_err(Error::from)?;
+    let profile = retrieve_profile_by_identity(
+        &connection,
+        &identity_type,
+        &value,
+        &scope,
+        query.into_inner().active,
+    )?;
+    let profile = scrub_profile(profile, &scope);
+    Ok(HttpResponse::Ok().json(profile))
+}
+
+/// History is served for stored profiles whose uuid is visible within `scope`.
+/// Unknown profiles are not found and erased ones are gone.
+fn check_history(connection: &PgConnection, uuid: Uuid, scope: &TrustType) -> Result<(), ApiError> {
+    let profile = retrieve_profile(connection, uuid, DisplayFilter::Any)?;
+    if scrub_profile(profile, scope).uuid.value.is_none() {
+        return Err(ApiError::Db(DBError::NotFound));
+    }
+    Ok(())
+}
+
+async fn user_history(
+    pool: web::Data<Pool>,
+    uuid: web::Path<Uuid>,
+    scope_and_user: ScopeAndUser,
+) -> Result<HttpResponse, ApiError> {
+    let uuid = uuid.into_inner();
+    let connection = pool.get().map_err(Error::from)?;
+    check_history(&connection, uuid, &TrustType::from(scope_and_user.scope))?;
+    let history = retrieve_history(&connection, uuid)?;
+    Ok(HttpResponse::Ok().json(history))
+}
+
+async fn user_history_version(
+    pool: web::Data<Pool>,
+    path: web::Path<(Uuid, i32)>,
+    scope_and_user: ScopeAndUser,
+) -> Result<HttpResponse, ApiError> {
+    let (uuid, version) = path.into_inner();
+    let scope = TrustType::from(scope_and_user.scope);
+    let connection = pool.get().map_err(Error::from)?;
+    check_history(&connection, uuid, &scope)?;
+    let profile = retrieve_history_profile(&connection, uuid, version)?;
+    let profile = scrub_profile(profile, &scope);
+    Ok(HttpResponse::Ok().json(profile))
+}
+
+async fn user_groups(
+    pool: web::Data<Pool>,
+    uuid: web::Path<Uuid>,
+    scope_and_user: ScopeAndUser,
+) -> Result<HttpResponse, ApiError> {
+    let connection = pool.get().map_err(Error::from)?;
+    let groups = retrieve_groups(
+        &connection,
+        uuid.into_inner(),
+        &TrustType::from(scope_and_user.scope),
+    )?;
+    Ok(HttpResponse::Ok().json(groups))
+}
+
+async fn group_members(
+    pool: web::Data<Pool>,
+    path: web::Path<(String, String)>,
+    query: web::Query<MembersQuery>,
+    scope_and_user: ScopeAndUser,
+) -> Result<HttpResponse, ApiError> {
+    let (provider, group) = path.into_inner();
+    let query = query.into_inner();
+    let after = query.after;
+    let connection = pool.get().map_err(Error::from)?;
+    let members = retrieve_group_members(
+        &connection,
+        &provider,
+        &group,
+        &TrustType::from(scope_and_user.scope),
+        query,
+    )?;
+    let next = members.last().cloned().or(after);
+    Ok(HttpResponse::Ok().json(json!({ "members": members, "next": next })))
+}
+
+fn scrub_entries(entries: Vec<ProfileEntry>, scope: &TrustType) -> Result<Vec<Profile>, Error> {
+    entries
+        .into_iter()
+        .map(|pe| {
+            serde_json::from_value(pe.profile)
+                .map(|p| scrub_profile(p, scope))
+                .map_err(Into::into)
+        })
+        .collect()
+}
+
+/// Filtering by a trust level above the caller's own would reveal who holds
+/// it.
+fn check_trust_filter(query: &UsersQuery, scope: &TrustType) -> Result<(), ApiError> {
+    match query.trust()? {
+        Some(ref trust) if trust > scope => Err(ApiError::Forbidden),
+        _ => Ok(()),
+    }
+}
+
+async fn users(
+    pool: web::Data<Pool>,
+    query: web::Query<UsersQuery>,
+    scope_and_user: ScopeAndUser,
+) -> Result<HttpResponse, ApiError> {
+    let query = query.into_inner();
+    let scope = TrustType::from(scope_and_user.scope);
+    check_trust_filter(&query, &scope)?;
+    let connection = pool.get().map_err(Error::from)?;
+    let entries = retrieve_profiles(&connection, &query)?;
+    let next = entries.last().map(|pe| pe.uuid).or(query.after);
+    let users = scrub_entries(entries, &scope)?;
+    Ok(HttpResponse::Ok().json(json!({ "users": users, "next": next })))
+}
+
+/// The next page of scrubbed profiles, one JSON document per line, and the
+/// uuid to continue after.
+fn users_page(
+    pool: &Pool,
+    query: &UsersQuery,
+    scope: &TrustType,
+) -> Result<Option<(Bytes, Uuid)>, Error> {
+    let connection = pool.get()?;
+    let entries = retrieve_profiles(&connection, query)?;
+    let last = match entries.last() {
+        Some(pe) => pe.uuid,
+        None => return Ok(None),
+    };
+    let mut page = vec![];
+    for p in scrub_entries(entries, scope)? {
+        serde_json::to_writer(&mut page, &p)?;
+        page.push(b'\n');
+    }
+    Ok(Some((Bytes::from(page), last)))
+}
+
+/// Streams all profiles matching `query` as NDJSON. `limit` sets the page
+/// size used to read them.
+async fn users_ndjson(
+    pool: web::Data<Pool>,
+    query: web::Query<UsersQuery>,
+    scope_and_user: ScopeAndUser,
+) -> Result<HttpResponse, ApiError> {
+    let query = query.into_inner();
+    let scope = TrustType::from(scope_and_user.scope);
+    check_trust_filter(&query, &scope)?;
+    let pool = pool.get_ref().clone();
+    let pages = stream::unfold(Some(query), move |query| {
+        let pool = pool.clone();
+        let scope = scope.clone();
+        async move {
+            let mut query = query?;
+            match users_page(&pool, &query, &scope) {
+                Ok(Some((page, last))) => {
+                    query.after = Some(last);
+                    Some((Ok(page), Some(query)))
+                }
+                Ok(None) => None,
+                Err(e) => Some((Err(ApiError::from(e)), None)),
+            }
+        }
+    });
+    Ok(HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .streaming(Box::pin(pages)))
+}
+
+async fn audit(
+    pool: web::Data<Pool>,
+    filter: web::Query<AuditFilter>,
+    scope_and_user: ScopeAndUser,
+) -> Result<HttpResponse, ApiError> {
+    if scope_and_user.scope != Trust::Staff {
+        return Err(ApiError::Forbidden);
+    }
+    let connection = pool.get().map_err(Error::from)?;
+    let scope = TrustType::from(scope_and_user.scope);
+    let changes = retrieve_changes(&connection, filter.into_inner())?
+        .into_iter()
+        .map(|c| scrub_change(c, &scope))
+        .collect::<Vec<_>>();
+    Ok(HttpResponse::Ok().json(changes))
 }
 
 fn index() -> HttpResponse {
@@ -18,7 +300,32 @@ fn version() -> HttpResponse {
 
 pub fn person_app() -> impl HttpServiceFactory {
     web::scope("/person/v2")
-        .service(web::resource("/user").route(web::post().to(change_user)))
+        .service(web::resource("/user/uuid/{uuid}").route(web::get().to(user_by_uuid)))
+        .service(web::resource("/user/uuid/{uuid}/history").route(web::get().to(user_history)))
+        .service(
+            web::resource("/user/uuid/{uuid}/history/{version}")
+                .route(web::get().to(user_history_version)),
+        )
+        .service(web::resource("/user/uuid/{uuid}/groups").route(web::get().to(user_groups)))
+        .service(
+            web::resource("/groups/{provider}/{group}/members").route(web::get().to(group_members)),
+        )
+        .service(web::resource("/user/user_id/{user_id}").route(web::get().to(user_by_user_id)))
+        .service(
+            web::resource("/user/primary_email/{primary_email}")
+                .route(web::get().to(user_by_primary_email)),
+        )
+        .service(
+            web::resource("/user/primary_username/{primary_username}")
+                .route(web::get().to(user_by_primary_username)),
+        )
+        .service(
+            web::resource("/user/identity/{identity_type}/{value}")
+                .route(web::get().to(user_by_identity)),
+        )
+        .service(web::resource("/users").route(web::get().to(users)))
+        .service(web::resource("/users/ndjson").route(web::get().to(users_ndjson)))
+        .service(web::resource("/audit").route(web::get().to(audit)))
         .service(web::resource("/version").to(version))
         .service(web::resource("").to(index))
 }
@@ -0,0 +1,90 @@
+use crate::db::types::DeliveryStatus;
+use crate::db::webhooks::insert_webhook;
+use crate::db::webhooks::replay;
+use crate::db::webhooks::retrieve_deliveries;
+use crate::db::webhooks::retrieve_webhooks;
+use crate::db::webhooks::NewWebhook;
+use crate::db::Pool;
+use crate::error::ApiError;
+use actix_web::dev::HttpServiceFactory;
+use actix_web::web;
+use actix_web::HttpResponse;
+use dino_park_gate::scope::ScopeAndUser;
+use dino_park_trust::GroupsTrust;
+use dino_park_trust::Trust;
+use failure::Error;
+use serde::Deserialize;
+use serde_json::json;
+
+#[derive(Deserialize)]
+pub struct DeliveriesQuery {
+    status: Option<DeliveryStatus>,
+}
+
+#[derive(Deserialize)]
+pub struct ReplayQuery {
+    after: Option<i64>,
+}
+
+fn check_admin(scope_and_user: &ScopeAndUser) -> Result<(), ApiError> {
+    if scope_and_user.scope != Trust::Staff || scope_and_user.groups_scope != GroupsTrust::Admin {
+        return Err(ApiError::Forbidden);
+    }
+    Ok(())
+}
+
+async fn register(
+    pool: web::Data<Pool>,
+    new: web::Json<NewWebhook>,
+    scope_and_user: ScopeAndUser,
+) -> Result<HttpResponse, ApiError> {
+    check_admin(&scope_and_user)?;
+    let connection = pool.get().map_err(Error::from)?;
+    let webhook = insert_webhook(&connection, new.into_inner())?;
+    Ok(HttpResponse::Ok().json(webhook))
+}
+
+async fn list(
+    pool: web::Data<Pool>,
+    scope_and_user: ScopeAndUser,
+) -> Result<HttpResponse, ApiError> {
+    check_admin(&scope_and_user)?;
+    let connection = pool.get().map_err(Error::from)?;
+    let webhooks = retrieve_webhooks(&connection)?;
+    Ok(HttpResponse::Ok().json(webhooks))
+}
+
+async fn deliveries(
+    pool: web::Data<Pool>,
+    id: web::Path<i32>,
+    query: web::Query<DeliveriesQuery>,
+    scope_and_user: ScopeAndUser,
+) -> Result<HttpResponse, ApiError> {
+    check_admin(&scope_and_user)?;
+    let connection = pool.get().map_err(Error::from)?;
+    let deliveries = retrieve_deliveries(&connection, id.into_inner(), query.into_inner().status)?;
+    Ok(HttpResponse::Ok().json(deliveries))
+}
+
+async fn replay_deliveries(
+    pool: web::Data<Pool>,
+    id: web::Path<i32>,
+    query: web::Query<ReplayQuery>,
+    scope_and_user: ScopeAndUser,
+) -> Result<HttpResponse, ApiError> {
+    check_admin(&scope_and_user)?;
+    let connection = pool.get().map_err(Error::from)?;
+    let replayed = replay(&connection, id.into_inner(), query.after)?;
+    Ok(HttpResponse::Ok().json(json!({ "replayed": replayed })))
+}
+
+pub fn webhooks_app() -> impl HttpServiceFactory {
+    web::scope("/webhooks/v2")
+        .service(
+            web::resource("")
+                .route(web::get().to(list))
+                .route(web::post().to(register)),
+        )
+        .service(web::resource("/{id}/deliveries").route(web::get().to(deliveries)))
+        .service(web::resource("/{id}/replay").route(web::post().to(replay_deliveries)))
+}
@@ -1,45 +1,378 @@
+use crate::db::model::groups_from_profile;
+use crate::db::model::identities_from_profile;
 use crate::db::model::try_from_profile;
+use crate::db::model::AccessGroup;
+use crate::db::model::InsertProfileChange;
+use crate::db::model::InsertProfileEvent;
+use crate::db::model::InsertProfileGroup;
+use crate::db::model::InsertProfileHistory;
+use crate::db::model::InsertProfileIdentity;
+use crate::db::model::InsertProfileTombstone;
+use crate::db::model::LinkedIdentity;
 use crate::db::model::ProfileEntry;
+use crate::db::model::ProfileEvent;
+use crate::db::model::ProfileTombstone;
+use crate::db::schema::profile_changes;
+use crate::db::schema::profile_events;
+use crate::db::schema::profile_groups;
+use crate::db::schema::profile_history;
+use crate::db::schema::profile_identities;
+use crate::db::schema::profile_tombstones;
 use crate::db::schema::profiles;
+use crate::db::types::DeletionType;
+use crate::db::types::EventType;
+use crate::db::types::OperationType;
+use crate::db::types::TrustType;
+use crate::error::DBError;
+use crate::error::ProfileError;
+use crate::profile::publishers::publisher_name;
+use crate::profile::update::FieldChange;
+use crate::profile::update::Operation;
+use cis_profile::schema::Display;
 use cis_profile::schema::Profile;
 use diesel::pg::PgConnection;
 use diesel::prelude::*;
+use diesel::sql_types::BigInt;
+use diesel::sql_types::Text;
 use failure::Error;
+use std::convert::TryFrom;
+use uuid::Uuid;
 
-const VERSION_CAP: i32 = 256;
+/// Linked identities are unique across profiles.
+const IDENTITY_CONFLICT: &str = "profile_identities_pkey";
 
-fn previous_version(v: i32) -> i32 {
-    if v < 0 {
-        0
-    } else {
-        (VERSION_CAP + v - 1) % VERSION_CAP
+const INSERT_CONFLICTS: [&str; 2] = ["profiles_pkey", "profiles_user_id_key"];
+
+/// Unique columns of `profiles` and the field they are taken from.
+const UNIQUE_FIELDS: [(&str, &str); 3] = [
+    ("profiles_user_id_key", "user_id"),
+    ("profiles_primary_email_key", "primary_email"),
+    ("profiles_primary_username_key", "primary_username"),
+];
+
+/// The channel committed profile events are sent to via `NOTIFY`.
+pub const PROFILE_CHANGED: &str = "profile_changed";
+
+/// Advisory lock serializing outbox writes so event ids become visible in
+/// order and consumers paging by id never skip an event.
+const OUTBOX_LOCK: i64 = 0x6470_635f_6576_7473;
+
+/// Versions increase monotonically. `0` denotes a profile which has not been
+/// stored yet.
+pub fn next_version(v: i32) -> i32 {
+    v.max(0) + 1
+}
+
+/// A concurrent writer created the same profile first.
+fn insert_conflict(e: diesel::result::Error) -> Error {
+    match e {
+        diesel::result::Error::DatabaseError(
+            diesel::result::DatabaseErrorKind::UniqueViolation,
+            ref info,
+        ) if info
+            .constraint_name()
+            .map(|c| INSERT_CONFLICTS.contains(&c))
+            .unwrap_or_default() =>
+        {
+            DBError::ConcurrentModification.into()
+        }
+        e => unique_violation(e),
+    }
+}
+
+/// Another profile already holds a unique value of the stored profile.
+fn unique_violation(e: diesel::result::Error) -> Error {
+    if let diesel::result::Error::DatabaseError(
+        diesel::result::DatabaseErrorKind::UniqueViolation,
+        ref info,
+    ) = e
+    {
+        if let Some((_, field)) = UNIQUE_FIELDS
+            .iter()
+            .find(|(constraint, _)| info.constraint_name() == Some(*constraint))
+        {
+            return DBError::DuplicateValue(String::from(*field)).into();
+        }
+    }
+    e.into()
+}
+
+fn publishers(changes: &[FieldChange]) -> Vec<String> {
+    let mut publishers: Vec<String> = vec![];
+    for change in changes.iter().filter(|c| c.operation != Operation::Noop) {
+        let name = publisher_name(&change.publisher);
+        if !publishers.contains(&name) {
+            publishers.push(name);
+        }
     }
+    publishers
+}
+
+fn display_level(display: &Option<Display>) -> Option<TrustType> {
+    display.clone().and_then(|d| TrustType::try_from(d).ok())
+}
+
+fn store_changes(
+    connection: &PgConnection,
+    pe: &ProfileEntry,
+    changes: &[FieldChange],
+) -> Result<(), Error> {
+    let changes = changes
+        .iter()
+        .filter_map(|change| {
+            OperationType::try_from(&change.operation)
+                .ok()
+                .map(|operation| InsertProfileChange {
+                    uuid: pe.uuid,
+                    version: pe.version,
+                    field: change.field.clone(),
+                    operation,
+                    publisher: publisher_name(&change.publisher),
+                    old_value: change.old.clone(),
+                    new_value: change.new.clone(),
+                    old_display: display_level(&change.old_display),
+                    new_display: display_level(&change.new_display),
+                })
+        })
+        .collect::<Vec<_>>();
+    diesel::insert_into(profile_changes::table)
+        .values(changes)
+        .execute(connection)?;
+    Ok(())
+}
+
+/// Adds an event to the outbox and notifies `PROFILE_CHANGED` listeners. Must
+/// run inside the transaction storing the change: the advisory lock is held
+/// and the notification is delivered once it commits.
+fn store_event(
+    connection: &PgConnection,
+    uuid: Uuid,
+    version: i32,
+    event: EventType,
+    fields: Vec<String>,
+    publishers: Vec<String>,
+) -> Result<(), Error> {
+    diesel::sql_query("SELECT pg_advisory_xact_lock($1)")
+        .bind::<BigInt, _>(OUTBOX_LOCK)
+        .execute(connection)?;
+    let event = diesel::insert_into(profile_events::table)
+        .values(InsertProfileEvent {
+            uuid,
+            version,
+            event,
+            fields,
+            publishers,
+        })
+        .get_result::<ProfileEvent>(connection)?;
+    diesel::sql_query("SELECT pg_notify($1, $2)")
+        .bind::<Text, _>(PROFILE_CHANGED)
+        .bind::<Text, _>(serde_json::to_string(&event)?)
+        .execute(connection)?;
+    Ok(())
+}
+
+/// Replaces the stored group memberships of `uuid` with `groups`.
+fn store_groups(
+    connection: &PgConnection,
+    uuid: Uuid,
+    groups: Vec<AccessGroup>,
+) -> Result<(), Error> {
+    diesel::delete(profile_groups::table.filter(profile_groups::uuid.eq(uuid)))
+        .execute(connection)?;
+    let groups = groups
+        .into_iter()
+        .map(|group| InsertProfileGroup {
+            uuid,
+            provider: String::from(group.provider),
+            group_name: group.group_name,
+            display: group.display,
+        })
+        .collect::<Vec<_>>();
+    diesel::insert_into(profile_groups::table)
+        .values(groups)
+        .execute(connection)?;
+    Ok(())
 }
 
-fn next_version(v: i32) -> i32 {
-    if v < 0 {
-        0
-    } else {
-        v + 1 % VERSION_CAP
+/// Replaces the stored linked identities of `uuid` with `identities`. Fails
+/// with `ProfileError::IdentityConflict` if another profile holds one of them.
+fn store_identities(
+    connection: &PgConnection,
+    uuid: Uuid,
+    identities: Vec<LinkedIdentity>,
+) -> Result<(), Error> {
+    diesel::delete(profile_identities::table.filter(profile_identities::uuid.eq(uuid)))
+        .execute(connection)?;
+    for identity in identities {
+        let identity_type = identity.identity_type;
+        diesel::insert_into(profile_identities::table)
+            .values(InsertProfileIdentity {
+                uuid,
+                identity_type: String::from(identity_type),
+                value: identity.value,
+                display: identity.display,
+            })
+            .execute(connection)
+            .map_err(|e| match e {
+                diesel::result::Error::DatabaseError(
+                    diesel::result::DatabaseErrorKind::UniqueViolation,
+                    ref info,
+                ) if info.constraint_name() == Some(IDENTITY_CONFLICT) => {
+                    ProfileError::IdentityConflict(String::from(identity_type)).into()
+                }
+                e => Error::from(e),
+            })?;
     }
+    Ok(())
+}
+
+fn changed_fields(changes: &[FieldChange]) -> Vec<String> {
+    changes
+        .iter()
+        .filter(|c| c.operation != Operation::Noop)
+        .map(|c| c.field.clone())
+        .collect()
+}
+
+/// Locks the stored profile `uuid` for the current transaction. Fails with
+/// `DBError::ConcurrentModification` if it is no longer at `version`.
+fn lock_version(
+    connection: &PgConnection,
+    uuid: Uuid,
+    version: i32,
+) -> Result<ProfileEntry, Error> {
+    profiles::table
+        .filter(profiles::uuid.eq(uuid))
+        .filter(profiles::version.eq(version))
+        .for_update()
+        .first::<ProfileEntry>(connection)
+        .optional()?
+        .ok_or_else(|| DBError::ConcurrentModification.into())
+}
+
+fn store_tombstone(
+    connection: &PgConnection,
+    uuid: Uuid,
+    version: i32,
+    deletion: DeletionType,
+    publisher: String,
+) -> Result<ProfileTombstone, Error> {
+    diesel::insert_into(profile_tombstones::table)
+        .values(InsertProfileTombstone {
+            uuid,
+            version,
+            deletion,
+            publisher,
+        })
+        .get_result::<ProfileTombstone>(connection)
+        .map_err(Into::into)
 }
 
+/// Stores `p` replacing `version`. The replaced profile is moved to
+/// `profile_history`, every change is added to the audit log, the group
+/// memberships and linked identities are re-indexed and an event is added to
+/// the outbox within the same transaction. Fails with `DBError::ConcurrentModification` if the
+/// stored profile is no longer at `version`.
 pub fn store_profile(
     connection: &PgConnection,
     p: Profile,
     version: i32,
-) -> Result<Profile, Error> {
+    changes: &[FieldChange],
+) -> Result<ProfileEntry, Error> {
+    let groups = groups_from_profile(&p);
+    let identities = identities_from_profile(&p);
     let i = try_from_profile(p, next_version(version))?;
-    let pe = if version == 0 {
-        diesel::insert_into(profiles::table)
-            .values(i)
-            .get_result::<ProfileEntry>(connection)?
-    } else {
-        diesel::update(profiles::table)
-            .filter(profiles::uuid.eq(i.uuid))
-            .filter(profiles::version.eq(previous_version(i.version)))
-            .set(i)
-            .get_result::<ProfileEntry>(connection)?
-    };
-    serde_json::from_value(pe.profile).map_err(Into::into)
+    connection.transaction::<_, Error, _>(|| {
+        let (pe, event) = if version == 0 {
+            let pe = diesel::insert_into(profiles::table)
+                .values(i)
+                .get_result::<ProfileEntry>(connection)
+                .map_err(insert_conflict)?;
+            (pe, EventType::Create)
+        } else {
+            let old = lock_version(connection, i.uuid, version)?;
+            diesel::insert_into(profile_history::table)
+                .values(InsertProfileHistory {
+                    uuid: old.uuid,
+                    version: old.version,
+                    replaced_by: publishers(changes),
+                    profile: old.profile,
+                })
+                .execute(connection)?;
+            let pe = diesel::update(profiles::table)
+                .filter(profiles::uuid.eq(i.uuid))
+                .filter(profiles::version.eq(version))
+                .set(i)
+                .get_result::<ProfileEntry>(connection)
+                .map_err(unique_violation)?;
+            (pe, EventType::Update)
+        };
+        store_changes(connection, &pe, changes)?;
+        store_groups(connection, pe.uuid, groups)?;
+        store_identities(connection, pe.uuid, identities)?;
+        store_event(
+            connection,
+            pe.uuid,
+            pe.version,
+            event,
+            changed_fields(changes),
+            publishers(changes),
+        )?;
+        Ok(pe)
+    })
+}
+
+/// Stores the stripped and deactivated profile `p` like [`store_profile`] and
+/// leaves a soft deletion tombstone for the new version. The history is
+/// dropped so the stripped attributes cannot be read from earlier versions.
+pub fn deactivate_profile(
+    connection: &PgConnection,
+    p: Profile,
+    version: i32,
+    changes: &[FieldChange],
+    publisher: String,
+) -> Result<ProfileTombstone, Error> {
+    connection.transaction::<_, Error, _>(|| {
+        let pe = store_profile(connection, p, version, changes)?;
+        diesel::delete(profile_history::table.filter(profile_history::uuid.eq(pe.uuid)))
+            .execute(connection)?;
+        store_tombstone(
+            connection,
+            pe.uuid,
+            pe.version,
+            DeletionType::Soft,
+            publisher,
+        )
+    })
+}
+
+/// Erases the profile `uuid` at `version` including its history and audit log.
+/// Only a hard deletion tombstone and an `erase` event remain.
+pub fn erase_profile(
+    connection: &PgConnection,
+    uuid: Uuid,
+    version: i32,
+    publisher: String,
+) -> Result<ProfileTombstone, Error> {
+    connection.transaction::<_, Error, _>(|| {
+        lock_version(connection, uuid, version)?;
+        diesel::delete(profile_changes::table.filter(profile_changes::uuid.eq(uuid)))
+            .execute(connection)?;
+        diesel::delete(profile_history::table.filter(profile_history::uuid.eq(uuid)))
+            .execute(connection)?;
+        diesel::delete(profile_groups::table.filter(profile_groups::uuid.eq(uuid)))
+            .execute(connection)?;
+        diesel::delete(profile_identities::table.filter(profile_identities::uuid.eq(uuid)))
+            .execute(connection)?;
+        diesel::delete(profiles::table.filter(profiles::uuid.eq(uuid))).execute(connection)?;
+        store_event(
+            connection,
+            uuid,
+            version,
+            EventType::Erase,
+            vec![],
+            vec![publisher.clone()],
+        )?;
+        store_tombstone(connection, uuid, version, DeletionType::Hard, publisher)
+    })
 }
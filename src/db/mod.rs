@@ -6,6 +6,7 @@ pub mod model;
 pub mod retrieve;
 pub mod schema;
 pub mod types;
+pub mod webhooks;
 
 pub type Pool = r2d2::Pool<ConnectionManager<PgConnection>>;
 
@@ -1,10 +1,13 @@
 use crate::db::schema::*;
 use crate::db::types::*;
 use crate::error::DBError;
+use chrono::NaiveDateTime;
 use cis_profile::schema::Profile;
 use failure::Error;
+use serde::Deserialize;
 use serde::Serialize;
 use serde_json::Value;
+use std::convert::TryFrom;
 use uuid::Uuid;
 
 const NDA: [&str; 2] = ["nda", "contingentworkernda"];
@@ -21,8 +24,266 @@ pub struct ProfileEntry {
     pub trust: TrustType,
     pub version: i32,
     pub profile: Value,
+    pub last_modified: NaiveDateTime,
 }
 
+/// The profile as it was at `version`. `replaced_by` and `replaced_at` belong
+/// to the write which replaced it with `version + 1`.
+#[derive(Queryable, PartialEq, Debug, Serialize)]
+pub struct ProfileHistoryEntry {
+    pub uuid: Uuid,
+    pub version: i32,
+    pub replaced_by: Vec<String>,
+    pub replaced_at: NaiveDateTime,
+    pub profile: Value,
+}
+
+#[derive(Queryable, PartialEq, Debug, Serialize)]
+pub struct ProfileHistorySummary {
+    pub uuid: Uuid,
+    pub version: i32,
+    pub replaced_by: Vec<String>,
+    pub replaced_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "profile_history"]
+pub struct InsertProfileHistory {
+    pub uuid: Uuid,
+    pub version: i32,
+    pub replaced_by: Vec<String>,
+    pub profile: Value,
+}
+
+#[derive(Clone, Queryable, PartialEq, Debug, Serialize)]
+pub struct ProfileChangeEntry {
+    pub id: i64,
+    pub uuid: Uuid,
+    pub version: i32,
+    pub field: String,
+    pub operation: OperationType,
+    pub publisher: String,
+    pub old_value: Value,
+    pub new_value: Value,
+    pub old_display: Option<TrustType>,
+    pub new_display: Option<TrustType>,
+    pub changed_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "profile_changes"]
+pub struct InsertProfileChange {
+    pub uuid: Uuid,
+    pub version: i32,
+    pub field: String,
+    pub operation: OperationType,
+    pub publisher: String,
+    pub old_value: Value,
+    pub new_value: Value,
+    pub old_display: Option<TrustType>,
+    pub new_display: Option<TrustType>,
+}
+
+#[derive(Queryable, PartialEq, Debug, Deserialize, Serialize)]
+pub struct ProfileEvent {
+    pub id: i64,
+    pub uuid: Uuid,
+    pub version: i32,
+    pub event: EventType,
+    pub fields: Vec<String>,
+    pub publishers: Vec<String>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "profile_events"]
+pub struct InsertProfileEvent {
+    pub uuid: Uuid,
+    pub version: i32,
+    pub event: EventType,
+    pub fields: Vec<String>,
+    pub publishers: Vec<String>,
+}
+
+#[derive(Queryable, PartialEq, Debug, Serialize)]
+pub struct ProfileTombstone {
+    pub uuid: Uuid,
+    pub version: i32,
+    pub deletion: DeletionType,
+    pub publisher: String,
+    pub deleted_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "profile_tombstones"]
+pub struct InsertProfileTombstone {
+    pub uuid: Uuid,
+    pub version: i32,
+    pub deletion: DeletionType,
+    pub publisher: String,
+}
+
+#[derive(Queryable, PartialEq, Debug, Serialize)]
+pub struct Webhook {
+    pub id: i32,
+    pub url: String,
+    #[serde(skip_serializing)]
+    pub secret: String,
+    pub uuid: Option<Uuid>,
+    pub field: Option<String>,
+    pub cursor: i64,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "webhooks"]
+pub struct InsertWebhook {
+    pub url: String,
+    pub secret: String,
+    pub uuid: Option<Uuid>,
+    pub field: Option<String>,
+    pub cursor: i64,
+}
+
+#[derive(Queryable, PartialEq, Debug, Serialize)]
+pub struct WebhookDelivery {
+    pub id: i64,
+    pub webhook_id: i32,
+    pub event_id: i64,
+    pub status: DeliveryStatus,
+    pub attempts: i32,
+    pub next_attempt_at: NaiveDateTime,
+    pub last_error: Option<String>,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "webhook_deliveries"]
+pub struct InsertWebhookDelivery {
+    pub webhook_id: i32,
+    pub event_id: i64,
+}
+
+#[derive(Insertable)]
+#[table_name = "profile_groups"]
+pub struct InsertProfileGroup {
+    pub uuid: Uuid,
+    pub provider: String,
+    pub group_name: String,
+    pub display: Option<TrustType>,
+}
+
+/// A group membership as found in `access_information.<provider>`.
+pub struct AccessGroup {
+    pub provider: &'static str,
+    pub group_name: String,
+    pub display: Option<TrustType>,
+}
+
+macro_rules! groups {
+    ($provider:ident, $p:ident, $groups:ident) => {
+        if let Some(ref values) = $p.access_information.$provider.values {
+            let display = $p
+                .access_information
+                .$provider
+                .metadata
+                .display
+                .clone()
+                .and_then(|d| TrustType::try_from(d).ok());
+            $groups.extend(values.0.keys().map(|group| AccessGroup {
+                provider: stringify!($provider),
+                group_name: group.clone(),
+                display: display.clone(),
+            }));
+        }
+    };
+}
+
+/// All access groups of `p`. Groups inherit the display level of their
+/// provider.
+pub fn groups_from_profile(p: &Profile) -> Vec<AccessGroup> {
+    let mut groups = vec![];
+    groups!(access_provider, p, groups);
+    groups!(ldap, p, groups);
+    groups!(hris, p, groups);
+    groups!(mozilliansorg, p, groups);
+    groups
+}
+
+#[derive(Insertable)]
+#[table_name = "profile_identities"]
+pub struct InsertProfileIdentity {
+    pub uuid: Uuid,
+    pub identity_type: String,
+    pub value: String,
+    pub display: Option<TrustType>,
+}
+
+/// A linked identity as found in `identities.<identity_type>`.
+pub struct LinkedIdentity {
+    pub identity_type: &'static str,
+    pub value: String,
+    pub display: Option<TrustType>,
+}
+
+macro_rules! identity {
+    ($identity_type:ident, $p:ident, $identities:ident) => {
+        if let Some(value) = $p
+            .identities
+            .$identity_type
+            .value
+            .as_ref()
+            .filter(|v| !v.is_empty())
+        {
+            $identities.push(LinkedIdentity {
+                identity_type: stringify!($identity_type),
+                value: value.clone(),
+                display: $p
+                    .identities
+                    .$identity_type
+                    .metadata
+                    .display
+                    .clone()
+                    .and_then(|d| TrustType::try_from(d).ok()),
+            });
+        }
+    };
+}
+
+/// Generates [`IDENTITIES`] and [`identities_from_profile`] from one list.
+macro_rules! identities {
+    ($($identity_type:ident),*) => {
+        /// The identity types of `identities`.
+        pub const IDENTITIES: &[&str] = &[$(stringify!($identity_type)),*];
+
+        /// All linked identities of `p`. Empty values link nothing and are skipped.
+        pub fn identities_from_profile(p: &Profile) -> Vec<LinkedIdentity> {
+            let mut identities = vec![];
+            $(identity!($identity_type, p, identities);)*
+            identities
+        }
+    };
+}
+
+identities!(
+    github_id_v3,
+    github_id_v4,
+    github_primary_email,
+    mozilliansorg_id,
+    bugzilla_mozilla_org_id,
+    bugzilla_mozilla_org_primary_email,
+    mozilla_ldap_id,
+    mozilla_ldap_primary_email,
+    mozilla_posix_id,
+    google_oauth2_id,
+    google_primary_email,
+    firefox_accounts_id,
+    firefox_accounts_primary_email,
+    custom_1_primary_email,
+    custom_2_primary_email,
+    custom_3_primary_email
+);
+
 fn trust_from(p: &Profile) -> TrustType {
     if let Some(true) = p.staff_information.staff.value {
         return TrustType::Staff;
@@ -46,6 +307,7 @@ pub fn try_from_profile(p: Profile, version: i32) -> Result<ProfileEntry, Error>
         (Some(uuid), Some(user_id), Some(primary_email), Some(primary_username), Some(active)) => {
             let trust = trust_from(&p);
             let uuid = Uuid::parse_str(&uuid)?;
+            let last_modified = p.last_modified.metadata.last_modified.naive_utc();
             Ok(ProfileEntry {
                 uuid,
                 user_id,
@@ -55,6 +317,7 @@ pub fn try_from_profile(p: Profile, version: i32) -> Result<ProfileEntry, Error>
                 trust,
                 version,
                 profile: serde_json::to_value(p)?,
+                last_modified,
             })
         }
         _ => Err(DBError::InvalidProfile.into()),
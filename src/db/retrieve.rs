@@ -1,21 +1,401 @@
+use crate::db::model::LinkedIdentity;
+use crate::db::model::ProfileChangeEntry;
 use crate::db::model::ProfileEntry;
+use crate::db::model::ProfileEvent;
+use crate::db::model::ProfileHistoryEntry;
+use crate::db::model::ProfileHistorySummary;
+use crate::db::model::IDENTITIES;
+use crate::db::schema::profile_changes;
+use crate::db::schema::profile_events;
+use crate::db::schema::profile_groups;
+use crate::db::schema::profile_history;
+use crate::db::schema::profile_identities;
+use crate::db::schema::profile_tombstones;
 use crate::db::schema::profiles;
+use crate::db::types::DeletionType;
+use crate::db::types::TrustType;
+use crate::error::DBError;
 use crate::profile::display::DisplayFilter;
+use chrono::NaiveDateTime;
 use cis_profile::schema::Profile;
+use diesel::dsl::exists;
 use diesel::pg::expression::dsl::any;
+use diesel::pg::Pg;
 use diesel::pg::PgConnection;
 use diesel::prelude::*;
 use failure::Error;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
 use uuid::Uuid;
 
+const DEFAULT_PAGE_LIMIT: i64 = 100;
+const MAX_PAGE_LIMIT: i64 = 1000;
+
+#[derive(Deserialize)]
+pub struct EventsQuery {
+    pub after: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct MembersQuery {
+    pub after: Option<Uuid>,
+    pub limit: Option<i64>,
+    #[serde(default)]
+    pub active: DisplayFilter,
+}
+
+#[derive(Deserialize)]
+pub struct UsersQuery {
+    pub after: Option<Uuid>,
+    pub limit: Option<i64>,
+    #[serde(default)]
+    pub active: DisplayFilter,
+    pub trust: Option<String>,
+    pub modified_since: Option<NaiveDateTime>,
+}
+
+impl UsersQuery {
+    pub fn trust(&self) -> Result<Option<TrustType>, Error> {
+        self.trust.clone().map(TrustType::try_from).transpose()
+    }
+}
+
+#[derive(Deserialize)]
+pub struct AuditFilter {
+    pub uuid: Option<Uuid>,
+    pub field: Option<String>,
+    pub publisher: Option<String>,
+    pub since: Option<NaiveDateTime>,
+    pub until: Option<NaiveDateTime>,
+    pub limit: Option<i64>,
+}
+
+/// The profile selected by `query` if its `active` state passes `filter`.
+fn retrieve_profile_by(
+    connection: &PgConnection,
+    query: profiles::BoxedQuery<'_, Pg>,
+    filter: DisplayFilter,
+) -> Result<Option<Profile>, Error> {
+    query
+        .filter(profiles::active.eq(any(filter.filter())))
+        .first::<ProfileEntry>(connection)
+        .optional()?
+        .map(|pe| serde_json::from_value(pe.profile))
+        .transpose()
+        .map_err(Into::into)
+}
+
 pub fn retrieve_profile(
     connection: &PgConnection,
     uuid: Uuid,
     filter: DisplayFilter,
 ) -> Result<Profile, Error> {
-    let pe = profiles::table
-        .filter(profiles::uuid.eq(uuid))
+    let query = profiles::table.filter(profiles::uuid.eq(uuid)).into_boxed();
+    match retrieve_profile_by(connection, query, filter)? {
+        Some(p) => Ok(p),
+        None if erased(connection, uuid)? => Err(DBError::Deleted.into()),
+        None => Err(DBError::NotFound.into()),
+    }
+}
+
+/// Whether the profile `uuid` has been hard deleted. Only lookups by uuid
+/// report erased profiles: tombstones keep nothing but the uuid, so an erased
+/// `user_id`, email or username cannot be told apart from an unknown one.
+pub fn erased(connection: &PgConnection, uuid: Uuid) -> Result<bool, Error> {
+    diesel::select(exists(
+        profile_tombstones::table
+            .filter(profile_tombstones::uuid.eq(uuid))
+            .filter(profile_tombstones::deletion.eq(DeletionType::Hard)),
+    ))
+    .get_result(connection)
+    .map_err(Into::into)
+}
+
+pub fn retrieve_profile_by_user_id(
+    connection: &PgConnection,
+    user_id: &str,
+    filter: DisplayFilter,
+) -> Result<Profile, Error> {
+    let query = profiles::table
+        .filter(profiles::user_id.eq(user_id))
+        .into_boxed();
+    retrieve_profile_by(connection, query, filter)?.ok_or_else(|| DBError::NotFound.into())
+}
+
+pub fn retrieve_profile_by_primary_email(
+    connection: &PgConnection,
+    primary_email: &str,
+    filter: DisplayFilter,
+) -> Result<Profile, Error> {
+    let query = profiles::table
+        .filter(profiles::primary_email.eq(primary_email))
+        .into_boxed();
+    retrieve_profile_by(connection, query, filter)?.ok_or_else(|| DBError::NotFound.into())
+}
+
+pub fn retrieve_profile_by_primary_username(
+    connection: &PgConnection,
+    primary_username: &str,
+    filter: DisplayFilter,
+) -> Result<Profile, Error> {
+    let query = profiles::table
+        .filter(profiles::primary_username.eq(primary_username))
+        .into_boxed();
+    retrieve_profile_by(connection, query, filter)?.ok_or_else(|| DBError::NotFound.into())
+}
+
+/// The profile linked to `value` at `identity_type`. Identities not visible
+/// at `scope` are never matched.
+pub fn retrieve_profile_by_identity(
+    connection: &PgConnection,
+    identity_type: &str,
+    value: &str,
+    scope: &TrustType,
+    filter: DisplayFilter,
+) -> Result<Profile, Error> {
+    if !IDENTITIES.contains(&identity_type) {
+        return Err(DBError::UnknownIdentityType.into());
+    }
+    let pe = profile_identities::table
+        .inner_join(profiles::table)
+        .filter(profile_identities::identity_type.eq(identity_type))
+        .filter(profile_identities::value.eq(value))
+        .filter(profile_identities::display.le(scope.clone()))
         .filter(profiles::active.eq(any(filter.filter())))
+        .select(profiles::all_columns)
         .first::<ProfileEntry>(connection)?;
     serde_json::from_value(pe.profile).map_err(Into::into)
 }
+
+/// The type of the first of `identities` linked to a profile other than
+/// `uuid`.
+pub fn identity_conflict(
+    connection: &PgConnection,
+    uuid: Uuid,
+    identities: &[LinkedIdentity],
+) -> Result<Option<&'static str>, Error> {
+    for identity in identities {
+        let taken: bool = diesel::select(exists(
+            profile_identities::table
+                .filter(profile_identities::identity_type.eq(identity.identity_type))
+                .filter(profile_identities::value.eq(&identity.value))
+                .filter(profile_identities::uuid.ne(uuid)),
+        ))
+        .get_result(connection)?;
+        if taken {
+            return Ok(Some(identity.identity_type));
+        }
+    }
+    Ok(None)
+}
+
+pub fn retrieve_profile_entry_by_user_id(
+    connection: &PgConnection,
+    user_id: &str,
+) -> Result<Option<ProfileEntry>, Error> {
+    profiles::table
+        .filter(profiles::user_id.eq(user_id))
+        .first::<ProfileEntry>(connection)
+        .optional()
+        .map_err(Into::into)
+}
+
+pub fn retrieve_profile_entry(
+    connection: &PgConnection,
+    uuid: Uuid,
+) -> Result<Option<ProfileEntry>, Error> {
+    profiles::table
+        .filter(profiles::uuid.eq(uuid))
+        .first::<ProfileEntry>(connection)
+        .optional()
+        .map_err(Into::into)
+}
+
+/// Whether `uuid` belongs to a stored or an erased profile.
+pub fn uuid_taken(connection: &PgConnection, uuid: Uuid) -> Result<bool, Error> {
+    let stored: bool = diesel::select(exists(profiles::table.filter(profiles::uuid.eq(uuid))))
+        .get_result(connection)?;
+    Ok(stored || erased(connection, uuid)?)
+}
+
+pub fn primary_username_taken(
+    connection: &PgConnection,
+    primary_username: &str,
+) -> Result<bool, Error> {
+    diesel::select(exists(
+        profiles::table.filter(profiles::primary_username.eq(primary_username)),
+    ))
+    .get_result(connection)
+    .map_err(Into::into)
+}
+
+pub fn retrieve_history(
+    connection: &PgConnection,
+    uuid: Uuid,
+) -> Result<Vec<ProfileHistorySummary>, Error> {
+    profile_history::table
+        .filter(profile_history::uuid.eq(uuid))
+        .select((
+            profile_history::uuid,
+            profile_history::version,
+            profile_history::replaced_by,
+            profile_history::replaced_at,
+        ))
+        .order_by(profile_history::version.desc())
+        .get_results::<ProfileHistorySummary>(connection)
+        .map_err(Into::into)
+}
+
+pub fn retrieve_history_profile(
+    connection: &PgConnection,
+    uuid: Uuid,
+    version: i32,
+) -> Result<Profile, Error> {
+    let he = profile_history::table
+        .filter(profile_history::uuid.eq(uuid))
+        .filter(profile_history::version.eq(version))
+        .first::<ProfileHistoryEntry>(connection)?;
+    serde_json::from_value(he.profile).map_err(Into::into)
+}
+
+pub fn retrieve_changes(
+    connection: &PgConnection,
+    filter: AuditFilter,
+) -> Result<Vec<ProfileChangeEntry>, Error> {
+    let mut query = profile_changes::table.into_boxed();
+    if let Some(uuid) = filter.uuid {
+        query = query.filter(profile_changes::uuid.eq(uuid));
+    }
+    if let Some(field) = filter.field {
+        query = query.filter(profile_changes::field.eq(field));
+    }
+    if let Some(publisher) = filter.publisher {
+        query = query.filter(profile_changes::publisher.eq(publisher));
+    }
+    if let Some(since) = filter.since {
+        query = query.filter(profile_changes::changed_at.ge(since));
+    }
+    if let Some(until) = filter.until {
+        query = query.filter(profile_changes::changed_at.lt(until));
+    }
+    let limit = filter
+        .limit
+        .unwrap_or(DEFAULT_PAGE_LIMIT)
+        .min(MAX_PAGE_LIMIT)
+        .max(1);
+    query
+        .order_by(profile_changes::id.desc())
+        .limit(limit)
+        .get_results::<ProfileChangeEntry>(connection)
+        .map_err(Into::into)
+}
+
+/// Events with an id greater than `after` in ascending order.
+pub fn retrieve_events(
+    connection: &PgConnection,
+    query: EventsQuery,
+) -> Result<Vec<ProfileEvent>, Error> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_LIMIT)
+        .min(MAX_PAGE_LIMIT)
+        .max(1);
+    profile_events::table
+        .filter(profile_events::id.gt(query.after.unwrap_or_default()))
+        .order_by(profile_events::id.asc())
+        .limit(limit)
+        .get_results::<ProfileEvent>(connection)
+        .map_err(Into::into)
+}
+
+/// Members of `group` at `provider` with an id greater than `query.after` in
+/// ascending order. Only memberships visible at `scope` are returned.
+pub fn retrieve_group_members(
+    connection: &PgConnection,
+    provider: &str,
+    group: &str,
+    scope: &TrustType,
+    query: MembersQuery,
+) -> Result<Vec<Uuid>, Error> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_LIMIT)
+        .min(MAX_PAGE_LIMIT)
+        .max(1);
+    let mut members = profile_groups::table
+        .inner_join(profiles::table)
+        .filter(profile_groups::provider.eq(provider))
+        .filter(profile_groups::group_name.eq(group))
+        .filter(profile_groups::display.le(scope.clone()))
+        .filter(profiles::active.eq(any(query.active.filter())))
+        .select(profile_groups::uuid)
+        .into_boxed();
+    if let Some(after) = query.after {
+        members = members.filter(profile_groups::uuid.gt(after));
+    }
+    members
+        .order_by(profile_groups::uuid.asc())
+        .limit(limit)
+        .get_results::<Uuid>(connection)
+        .map_err(Into::into)
+}
+
+/// The groups of the profile `uuid` per provider. Only memberships visible at
+/// `scope` are returned.
+pub fn retrieve_groups(
+    connection: &PgConnection,
+    uuid: Uuid,
+    scope: &TrustType,
+) -> Result<BTreeMap<String, Vec<String>>, Error> {
+    let stored: bool = diesel::select(exists(profiles::table.filter(profiles::uuid.eq(uuid))))
+        .get_result(connection)?;
+    if !stored {
+        return Err(if erased(connection, uuid)? {
+            DBError::Deleted.into()
+        } else {
+            DBError::NotFound.into()
+        });
+    }
+    let memberships = profile_groups::table
+        .filter(profile_groups::uuid.eq(uuid))
+        .filter(profile_groups::display.le(scope.clone()))
+        .select((profile_groups::provider, profile_groups::group_name))
+        .order_by((profile_groups::provider, profile_groups::group_name))
+        .get_results::<(String, String)>(connection)?;
+    let mut groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (provider, group) in memberships {
+        groups.entry(provider).or_default().push(group);
+    }
+    Ok(groups)
+}
+
+/// Profiles with a uuid greater than `query.after` in ascending order.
+pub fn retrieve_profiles(
+    connection: &PgConnection,
+    query: &UsersQuery,
+) -> Result<Vec<ProfileEntry>, Error> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_LIMIT)
+        .min(MAX_PAGE_LIMIT)
+        .max(1);
+    let mut profiles = profiles::table
+        .filter(profiles::active.eq(any(query.active.filter())))
+        .into_boxed();
+    if let Some(after) = query.after {
+        profiles = profiles.filter(profiles::uuid.gt(after));
+    }
+    if let Some(trust) = query.trust()? {
+        profiles = profiles.filter(profiles::trust.eq(trust));
+    }
+    if let Some(modified_since) = query.modified_since {
+        profiles = profiles.filter(profiles::last_modified.ge(modified_since));
+    }
+    profiles
+        .order_by(profiles::uuid.asc())
+        .limit(limit)
+        .get_results::<ProfileEntry>(connection)
+        .map_err(Into::into)
+}
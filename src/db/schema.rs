@@ -1,3 +1,87 @@
+table! {
+    use diesel::sql_types::*;
+    use crate::db::types::*;
+
+    profile_changes (id) {
+        id -> Int8,
+        uuid -> Uuid,
+        version -> Int4,
+        field -> Varchar,
+        operation -> Operation_type,
+        publisher -> Varchar,
+        old_value -> Jsonb,
+        new_value -> Jsonb,
+        old_display -> Nullable<Trust_type>,
+        new_display -> Nullable<Trust_type>,
+        changed_at -> Timestamp,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::db::types::*;
+
+    profile_events (id) {
+        id -> Int8,
+        uuid -> Uuid,
+        version -> Int4,
+        event -> Event_type,
+        fields -> Array<Varchar>,
+        publishers -> Array<Varchar>,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::db::types::*;
+
+    profile_groups (provider, group_name, uuid) {
+        uuid -> Uuid,
+        provider -> Varchar,
+        group_name -> Varchar,
+        display -> Nullable<Trust_type>,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::db::types::*;
+
+    profile_history (uuid, version) {
+        uuid -> Uuid,
+        version -> Int4,
+        replaced_by -> Array<Varchar>,
+        replaced_at -> Timestamp,
+        profile -> Jsonb,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::db::types::*;
+
+    profile_identities (identity_type, value) {
+        uuid -> Uuid,
+        identity_type -> Varchar,
+        value -> Varchar,
+        display -> Nullable<Trust_type>,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::db::types::*;
+
+    profile_tombstones (uuid, version, deletion) {
+        uuid -> Uuid,
+        version -> Int4,
+        deletion -> Deletion_type,
+        publisher -> Varchar,
+        deleted_at -> Timestamp,
+    }
+}
+
 table! {
     use diesel::sql_types::*;
     use crate::db::types::*;
@@ -11,5 +95,52 @@ table! {
         trust -> Trust_type,
         version -> Int4,
         profile -> Jsonb,
+        last_modified -> Timestamp,
     }
 }
+
+table! {
+    use diesel::sql_types::*;
+    use crate::db::types::*;
+
+    webhook_deliveries (id) {
+        id -> Int8,
+        webhook_id -> Int4,
+        event_id -> Int8,
+        status -> Delivery_status,
+        attempts -> Int4,
+        next_attempt_at -> Timestamp,
+        last_error -> Nullable<Varchar>,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::db::types::*;
+
+    webhooks (id) {
+        id -> Int4,
+        url -> Varchar,
+        secret -> Varchar,
+        uuid -> Nullable<Uuid>,
+        field -> Nullable<Varchar>,
+        cursor -> Int8,
+        created_at -> Timestamp,
+    }
+}
+
+joinable!(profile_groups -> profiles (uuid));
+joinable!(profile_identities -> profiles (uuid));
+joinable!(webhook_deliveries -> profile_events (event_id));
+joinable!(webhook_deliveries -> webhooks (webhook_id));
+
+allow_tables_to_appear_in_same_query!(
+    profile_events,
+    profile_groups,
+    profile_history,
+    profile_identities,
+    profiles,
+    webhook_deliveries,
+    webhooks,
+);
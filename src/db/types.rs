@@ -1,4 +1,5 @@
 use crate::error::DBError;
+use crate::profile::update::Operation;
 use cis_profile::schema::Display;
 use dino_park_trust::Trust;
 use serde::Deserialize;
@@ -15,6 +16,46 @@ pub enum TrustType {
     Staff,
 }
 
+#[derive(Clone, DbEnum, Debug, Deserialize, PartialEq, Serialize)]
+#[DieselType = "Operation_type"]
+#[serde(rename_all = "lowercase")]
+pub enum OperationType {
+    Create,
+    Update,
+}
+
+#[derive(Clone, DbEnum, Debug, Deserialize, PartialEq, Serialize)]
+#[DieselType = "Event_type"]
+#[serde(rename_all = "lowercase")]
+pub enum EventType {
+    Create,
+    Update,
+    Erase,
+}
+
+#[derive(Clone, DbEnum, Debug, Deserialize, PartialEq, Serialize)]
+#[DieselType = "Delivery_status"]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryStatus {
+    Pending,
+    Delivered,
+    Dead,
+}
+
+#[derive(Clone, DbEnum, Debug, Deserialize, PartialEq, Serialize)]
+#[DieselType = "Deletion_type"]
+#[serde(rename_all = "lowercase")]
+pub enum DeletionType {
+    Soft,
+    Hard,
+}
+
+impl Default for DeletionType {
+    fn default() -> Self {
+        DeletionType::Soft
+    }
+}
+
 impl From<Trust> for TrustType {
     fn from(t: Trust) -> Self {
         match t {
@@ -41,6 +82,17 @@ impl TryFrom<String> for TrustType {
     }
 }
 
+impl TryFrom<&Operation> for OperationType {
+    type Error = failure::Error;
+    fn try_from(o: &Operation) -> Result<Self, Self::Error> {
+        match o {
+            Operation::Create => Ok(OperationType::Create),
+            Operation::Update => Ok(OperationType::Update),
+            Operation::Noop => Err(DBError::NotApplicable.into()),
+        }
+    }
+}
+
 impl TryFrom<Display> for TrustType {
     type Error = failure::Error;
     fn try_from(d: Display) -> Result<Self, Self::Error> {
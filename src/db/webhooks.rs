@@ -0,0 +1,274 @@
+use crate::db::model::InsertWebhook;
+use crate::db::model::InsertWebhookDelivery;
+use crate::db::model::ProfileEvent;
+use crate::db::model::Webhook;
+use crate::db::model::WebhookDelivery;
+use crate::db::schema::profile_events;
+use crate::db::schema::webhook_deliveries;
+use crate::db::schema::webhooks;
+use crate::db::types::DeliveryStatus;
+use crate::error::DBError;
+use crate::notify::StreamFilter;
+use crate::settings::WebhookSettings;
+use diesel::dsl::max;
+use diesel::dsl::now;
+use diesel::dsl::IntervalDsl;
+use diesel::pg::expression::dsl::any;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use failure::Error;
+use serde::Deserialize;
+use uuid::Uuid;
+
+const FAN_OUT_BATCH: i64 = 100;
+const LEASE_SECONDS: i32 = 60;
+const MAX_BACKOFF_SECONDS: i64 = 3600;
+const MAX_DELIVERIES: i64 = 1000;
+
+#[derive(Deserialize)]
+pub struct NewWebhook {
+    pub url: String,
+    pub secret: String,
+    pub uuid: Option<Uuid>,
+    pub field: Option<String>,
+}
+
+impl Webhook {
+    pub fn filter(&self) -> StreamFilter {
+        StreamFilter {
+            uuid: self.uuid,
+            field: self.field.clone(),
+        }
+    }
+}
+
+/// Registers a webhook. It receives events stored after its registration.
+pub fn insert_webhook(connection: &PgConnection, new: NewWebhook) -> Result<Webhook, Error> {
+    let cursor = profile_events::table
+        .select(max(profile_events::id))
+        .first::<Option<i64>>(connection)?
+        .unwrap_or_default();
+    diesel::insert_into(webhooks::table)
+        .values(InsertWebhook {
+            url: new.url,
+            secret: new.secret,
+            uuid: new.uuid,
+            field: new.field,
+            cursor,
+        })
+        .get_result(connection)
+        .map_err(Into::into)
+}
+
+pub fn retrieve_webhooks(connection: &PgConnection) -> Result<Vec<Webhook>, Error> {
+    webhooks::table
+        .order_by(webhooks::id.asc())
+        .get_results(connection)
+        .map_err(Into::into)
+}
+
+pub fn retrieve_deliveries(
+    connection: &PgConnection,
+    webhook_id: i32,
+    status: Option<DeliveryStatus>,
+) -> Result<Vec<WebhookDelivery>, Error> {
+    let mut query = webhook_deliveries::table
+        .filter(webhook_deliveries::webhook_id.eq(webhook_id))
+        .into_boxed();
+    if let Some(status) = status {
+        query = query.filter(webhook_deliveries::status.eq(status));
+    }
+    query
+        .order_by(webhook_deliveries::id.desc())
+        .limit(MAX_DELIVERIES)
+        .get_results(connection)
+        .map_err(Into::into)
+}
+
+/// Queues deliveries again. With `after` every event after that id is sent
+/// again, otherwise all dead deliveries are retried. Returns the number of
+/// re-queued deliveries.
+pub fn replay(
+    connection: &PgConnection,
+    webhook_id: i32,
+    after: Option<i64>,
+) -> Result<usize, Error> {
+    connection.transaction::<_, Error, _>(|| {
+        webhooks::table
+            .filter(webhooks::id.eq(webhook_id))
+            .for_update()
+            .first::<Webhook>(connection)
+            .optional()?
+            .ok_or(DBError::NotFound)?;
+        let deliveries = webhook_deliveries::table
+            .filter(webhook_deliveries::webhook_id.eq(webhook_id))
+            .into_boxed();
+        let deliveries = match after {
+            Some(after) => {
+                diesel::update(webhooks::table.filter(webhooks::id.eq(webhook_id)))
+                    .set(webhooks::cursor.eq(after))
+                    .execute(connection)?;
+                deliveries.filter(webhook_deliveries::event_id.gt(after))
+            }
+            None => deliveries.filter(webhook_deliveries::status.eq(DeliveryStatus::Dead)),
+        };
+        let ids = deliveries
+            .select(webhook_deliveries::id)
+            .get_results::<i64>(connection)?;
+        diesel::update(webhook_deliveries::table.filter(webhook_deliveries::id.eq(any(ids))))
+            .set((
+                webhook_deliveries::status.eq(DeliveryStatus::Pending),
+                webhook_deliveries::attempts.eq(0),
+                webhook_deliveries::next_attempt_at.eq(now),
+                webhook_deliveries::last_error.eq(None::<String>),
+                webhook_deliveries::updated_at.eq(now),
+            ))
+            .execute(connection)
+            .map_err(Into::into)
+    })
+}
+
+/// Creates deliveries for all events after each webhook's cursor matching
+/// its filter and advances the cursor.
+pub fn fan_out(connection: &PgConnection) -> Result<(), Error> {
+    let ids = webhooks::table
+        .select(webhooks::id)
+        .get_results::<i32>(connection)?;
+    for id in ids {
+        connection.transaction::<_, Error, _>(|| {
+            let webhook = match webhooks::table
+                .filter(webhooks::id.eq(id))
+                .for_update()
+                .skip_locked()
+                .first::<Webhook>(connection)
+                .optional()?
+            {
+                Some(webhook) => webhook,
+                None => return Ok(()),
+            };
+            let events = profile_events::table
+                .filter(profile_events::id.gt(webhook.cursor))
+                .order_by(profile_events::id.asc())
+                .limit(FAN_OUT_BATCH)
+                .get_results::<ProfileEvent>(connection)?;
+            let cursor = match events.last() {
+                Some(event) => event.id,
+                None => return Ok(()),
+            };
+            let filter = webhook.filter();
+            let deliveries = events
+                .iter()
+                .filter(|event| filter.matches(event))
+                .map(|event| InsertWebhookDelivery {
+                    webhook_id: webhook.id,
+                    event_id: event.id,
+                })
+                .collect::<Vec<_>>();
+            diesel::insert_into(webhook_deliveries::table)
+                .values(deliveries)
+                .on_conflict_do_nothing()
+                .execute(connection)?;
+            diesel::update(webhooks::table.filter(webhooks::id.eq(webhook.id)))
+                .set(webhooks::cursor.eq(cursor))
+                .execute(connection)?;
+            Ok(())
+        })?;
+    }
+    Ok(())
+}
+
+/// Claims up to `limit` due deliveries by leasing them for `LEASE_SECONDS`
+/// so other workers skip them while they are attempted.
+pub fn claim_due(
+    connection: &PgConnection,
+    limit: i64,
+) -> Result<Vec<(WebhookDelivery, Webhook, ProfileEvent)>, Error> {
+    connection.transaction::<_, Error, _>(|| {
+        let ids = webhook_deliveries::table
+            .select(webhook_deliveries::id)
+            .filter(webhook_deliveries::status.eq(DeliveryStatus::Pending))
+            .filter(webhook_deliveries::next_attempt_at.le(now))
+            .order_by(webhook_deliveries::next_attempt_at.asc())
+            .limit(limit)
+            .for_update()
+            .skip_locked()
+            .get_results::<i64>(connection)?;
+        diesel::update(
+            webhook_deliveries::table.filter(webhook_deliveries::id.eq(any(ids.clone()))),
+        )
+        .set(webhook_deliveries::next_attempt_at.eq(now + LEASE_SECONDS.seconds()))
+        .execute(connection)?;
+        webhook_deliveries::table
+            .inner_join(webhooks::table)
+            .inner_join(profile_events::table)
+            .filter(webhook_deliveries::id.eq(any(ids)))
+            .order_by(webhook_deliveries::id.asc())
+            .get_results(connection)
+            .map_err(Into::into)
+    })
+}
+
+/// Exponential backoff after the `attempts`th failed attempt.
+pub fn backoff_seconds(settings: &WebhookSettings, attempts: i32) -> i64 {
+    let factor = 1i64
+        .checked_shl(attempts.max(1) as u32 - 1)
+        .unwrap_or(i64::MAX);
+    settings
+        .backoff_seconds
+        .saturating_mul(factor)
+        .min(MAX_BACKOFF_SECONDS)
+}
+
+/// Records the outcome of an attempt. Failed deliveries are retried with
+/// exponential backoff until `max_attempts` is reached and they are dead.
+/// Returns `false` without recording anything if the lease taken by
+/// [`claim_due`] expired and the delivery may have been claimed again.
+pub fn record_attempt(
+    connection: &PgConnection,
+    delivery: &WebhookDelivery,
+    error: Option<String>,
+    settings: &WebhookSettings,
+) -> Result<bool, Error> {
+    let attempts = delivery.attempts + 1;
+    let target = webhook_deliveries::table
+        .filter(webhook_deliveries::id.eq(delivery.id))
+        .filter(webhook_deliveries::status.eq(DeliveryStatus::Pending))
+        .filter(webhook_deliveries::attempts.eq(delivery.attempts))
+        .filter(webhook_deliveries::next_attempt_at.eq(delivery.next_attempt_at))
+        .filter(webhook_deliveries::next_attempt_at.gt(now));
+    let status = match error {
+        None => DeliveryStatus::Delivered,
+        Some(_) if attempts >= settings.max_attempts => DeliveryStatus::Dead,
+        Some(_) => DeliveryStatus::Pending,
+    };
+    let backoff = backoff_seconds(settings, attempts) as i32;
+    diesel::update(target)
+        .set((
+            webhook_deliveries::status.eq(status),
+            webhook_deliveries::attempts.eq(attempts),
+            webhook_deliveries::next_attempt_at.eq(now + backoff.seconds()),
+            webhook_deliveries::last_error.eq(error),
+            webhook_deliveries::updated_at.eq(now),
+        ))
+        .execute(connection)
+        .map(|updated| updated == 1)
+        .map_err(Into::into)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let settings = WebhookSettings {
+            max_attempts: 8,
+            backoff_seconds: 10,
+            poll_interval_ms: 1000,
+        };
+        assert_eq!(backoff_seconds(&settings, 1), 10);
+        assert_eq!(backoff_seconds(&settings, 2), 20);
+        assert_eq!(backoff_seconds(&settings, 4), 80);
+        assert_eq!(backoff_seconds(&settings, 100), MAX_BACKOFF_SECONDS);
+    }
+}
@@ -1,3 +1,13 @@
+use crate::profile::validate_rules::Issue;
+use actix_web::http::StatusCode;
+use actix_web::HttpResponse;
+use actix_web::ResponseError;
+use cis_profile::schema::PublisherAuthority;
+use log::error;
+use serde::Serialize;
+use serde::Serializer;
+use serde_json::json;
+
 #[derive(Fail, Debug, PartialEq)]
 pub enum DBError {
     #[fail(display = "db_invalid_profile_v2")]
@@ -6,6 +16,38 @@ pub enum DBError {
     InvalidTrustLevel,
     #[fail(display = "not_applicable")]
     NotApplicable,
+    #[fail(display = "profile_not_found")]
+    NotFound,
+    #[fail(display = "concurrent_modification")]
+    ConcurrentModification,
+    #[fail(display = "profile_deleted")]
+    Deleted,
+    #[fail(display = "unknown_identity_type")]
+    UnknownIdentityType,
+    #[fail(display = "duplicate_value")]
+    DuplicateValue(String),
+}
+
+impl ResponseError for DBError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::InvalidProfile
+            | Self::InvalidTrustLevel
+            | Self::NotApplicable
+            | Self::UnknownIdentityType => StatusCode::BAD_REQUEST,
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::ConcurrentModification | Self::DuplicateValue(_) => StatusCode::CONFLICT,
+            Self::Deleted => StatusCode::GONE,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let body = match self {
+            Self::DuplicateValue(field) => json!({ "error": self.to_string(), "field": field }),
+            _ => json!({ "error": self.to_string() }),
+        };
+        HttpResponse::build(self.status_code()).json(body)
+    }
 }
 
 #[derive(Fail, Debug, PartialEq)]
@@ -16,10 +58,64 @@ pub enum ProfileError {
     PublisherNotAllowedToCreate,
     #[fail(display = "publisher_not_allowed_to_update")]
     PublisherNotAllowedToUpdate,
+    #[fail(display = "publisher_not_allowed_to_delete")]
+    PublisherNotAllowedToDelete,
+    #[fail(display = "invalid_signature")]
+    InvalidSignature,
+    #[fail(display = "identity_conflict")]
+    IdentityConflict(String),
     #[fail(display = "unknown_error")]
     UnknownError,
 }
 
+impl ResponseError for ProfileError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::OutdatedUpdate | Self::IdentityConflict(_) => StatusCode::CONFLICT,
+            Self::PublisherNotAllowedToCreate
+            | Self::PublisherNotAllowedToUpdate
+            | Self::PublisherNotAllowedToDelete
+            | Self::InvalidSignature => StatusCode::FORBIDDEN,
+            Self::UnknownError => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let body = match self {
+            Self::IdentityConflict(identity) => {
+                json!({ "error": self.to_string(), "identity": identity })
+            }
+            _ => json!({ "error": self.to_string() }),
+        };
+        HttpResponse::build(self.status_code()).json(body)
+    }
+}
+
+impl Serialize for ProfileError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// A rejected update with the offending field path and its publisher.
+#[derive(Fail, Debug, PartialEq, Serialize)]
+#[fail(display = "{} ({} by {:?})", error, field, publisher)]
+pub struct UpdateError {
+    pub error: ProfileError,
+    pub field: String,
+    pub publisher: PublisherAuthority,
+}
+
+impl ResponseError for UpdateError {
+    fn status_code(&self) -> StatusCode {
+        self.error.status_code()
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(self)
+    }
+}
+
 #[derive(Debug, Fail)]
 pub enum SecretsError {
     #[fail(display = "invalid sign key source: use 'none', 'file' or 'ssm'")]
@@ -27,3 +123,74 @@ pub enum SecretsError {
     #[fail(display = "invalid sign key source: use 'none', 'file' or 'ssm'")]
     UseNoneFileSsmWellKnown,
 }
+
+#[derive(Debug, Fail)]
+pub enum RulesError {
+    #[fail(display = "invalid publisher rules source: use 'remote', 'file' or 'inline'")]
+    UseRemoteFileInline,
+    #[fail(display = "publisher rules unavailable: {}", _0)]
+    Unavailable(String),
+    #[fail(display = "invalid publisher rules: {:?}", _0)]
+    Invalid(Vec<Issue>),
+}
+
+#[derive(Fail, Debug)]
+pub enum ApiError {
+    #[fail(display = "{}", _0)]
+    Db(DBError),
+    #[fail(display = "{}", _0)]
+    Profile(ProfileError),
+    #[fail(display = "{}", _0)]
+    RejectedUpdate(UpdateError),
+    #[fail(display = "forbidden")]
+    Forbidden,
+    #[fail(display = "internal_error: {}", _0)]
+    Internal(failure::Error),
+}
+
+impl From<failure::Error> for ApiError {
+    fn from(e: failure::Error) -> Self {
+        let e = match e.downcast::<UpdateError>() {
+            Ok(e) => return ApiError::RejectedUpdate(e),
+            Err(e) => e,
+        };
+        let e = match e.downcast::<ProfileError>() {
+            Ok(e) => return ApiError::Profile(e),
+            Err(e) => e,
+        };
+        let e = match e.downcast::<DBError>() {
+            Ok(e) => return ApiError::Db(e),
+            Err(e) => e,
+        };
+        match e.downcast::<diesel::result::Error>() {
+            Ok(diesel::result::Error::NotFound) => ApiError::Db(DBError::NotFound),
+            Ok(e) => ApiError::Internal(e.into()),
+            Err(e) => ApiError::Internal(e),
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::Db(e) => e.status_code(),
+            Self::Profile(e) => e.status_code(),
+            Self::RejectedUpdate(e) => e.status_code(),
+            Self::Forbidden => StatusCode::FORBIDDEN,
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            Self::Db(e) => e.error_response(),
+            Self::Profile(e) => e.error_response(),
+            Self::RejectedUpdate(e) => e.error_response(),
+            Self::Forbidden => HttpResponse::Forbidden().json(json!({ "error": "forbidden" })),
+            Self::Internal(e) => {
+                error!("internal error: {}", e);
+                HttpResponse::InternalServerError().json(json!({ "error": "internal_error" }))
+            }
+        }
+    }
+}
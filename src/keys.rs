@@ -1,13 +1,29 @@
 use crate::error::SecretsError;
 use crate::settings::CisSettings;
 use crate::settings::Keys;
+use base64::encode_config;
+use base64::URL_SAFE_NO_PAD;
 use cis_profile::crypto::SecretStore;
 use failure::Error;
+use log::warn;
+use openssl::rsa::Rsa;
+use serde::Serialize;
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::BufReader;
 
 pub async fn get_store_from_settings(settings: &CisSettings) -> Result<SecretStore, Error> {
+    get_store_and_jwks_from_settings(settings)
+        .await
+        .map(|(store, _)| store)
+}
+
+/// Loads the secret store and a JWKS of the verify keys it was loaded with.
+/// Publishing is best effort: keys which cannot be exported are left out of
+/// the JWKS with a warning.
+pub async fn get_store_and_jwks_from_settings(
+    settings: &CisSettings,
+) -> Result<(SecretStore, JwkSet), Error> {
     let mut store = SecretStore::default();
     store = match settings.sign_keys.source.as_str() {
         "none" => store,
@@ -15,19 +31,79 @@ pub async fn get_store_from_settings(settings: &CisSettings) -> Result<SecretSto
         "ssm" => add_sign_keys_from_ssm(&settings.sign_keys, store).await?,
         _ => return Err(SecretsError::UseNoneFileSsm.into()),
     };
-    store = match (
+    let (store, jwks) = match (
         settings.verify_keys.source.as_str(),
         &settings.verify_keys.well_known_iam_endpoint,
     ) {
-        ("none", _) => store,
-        ("file", _) => add_verify_keys_from_files(&settings.verify_keys, store)?,
-        ("ssm", _) => add_verify_keys_from_ssm(&settings.verify_keys, store).await?,
-        ("well_known", Some(url)) => store.with_verify_keys_from_well_known(&url).await?,
+        ("none", _) => (store, JwkSet::default()),
+        ("file", _) => {
+            let key_tuples = read_key_files(&settings.verify_keys)?;
+            let jwks = jwks_from_pems(&key_tuples);
+            (store.with_verify_keys_from_inline_iter(key_tuples)?, jwks)
+        }
+        ("ssm", _) => (
+            add_verify_keys_from_ssm(&settings.verify_keys, store).await?,
+            JwkSet::default(),
+        ),
+        ("well_known", Some(url)) => (
+            store.with_verify_keys_from_well_known(&url).await?,
+            JwkSet::default(),
+        ),
         _ => {
             return Err(SecretsError::UseNoneFileSsmWellKnown.into());
         }
     };
-    Ok(store)
+    if jwks.keys.is_empty() {
+        warn!(
+            "no verify keys from '{}' to publish as JWKS",
+            settings.verify_keys.source
+        );
+    }
+    Ok((store, jwks))
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct JwkSet {
+    pub keys: Vec<Jwk>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Jwk {
+    pub kty: String,
+    pub alg: String,
+    #[serde(rename = "use")]
+    pub use_: String,
+    pub kid: String,
+    pub n: String,
+    pub e: String,
+}
+
+/// Builds a JWKS with the publisher names as `kid` from `(publisher, pem)`
+/// tuples. Keys which are not RSA public keys are skipped.
+pub fn jwks_from_pems(key_tuples: &[(String, String)]) -> JwkSet {
+    let keys = key_tuples
+        .iter()
+        .filter_map(|(k, pem)| match jwk_from_pem(k.clone(), pem) {
+            Ok(jwk) => Some(jwk),
+            Err(e) => {
+                warn!("unable to publish verify key for {}: {}", k, e);
+                None
+            }
+        })
+        .collect();
+    JwkSet { keys }
+}
+
+fn jwk_from_pem(kid: String, pem: &str) -> Result<Jwk, Error> {
+    let rsa = Rsa::public_key_from_pem(pem.as_bytes())?;
+    Ok(Jwk {
+        kty: String::from("RSA"),
+        alg: String::from("RS256"),
+        use_: String::from("sig"),
+        kid,
+        n: encode_config(rsa.n().to_vec(), URL_SAFE_NO_PAD),
+        e: encode_config(rsa.e().to_vec(), URL_SAFE_NO_PAD),
+    })
 }
 
 pub async fn add_sign_keys_from_ssm(keys: &Keys, store: SecretStore) -> Result<SecretStore, Error> {
@@ -44,19 +120,15 @@ pub async fn add_verify_keys_from_ssm(
 }
 
 pub fn add_sign_keys_from_files(keys: &Keys, store: SecretStore) -> Result<SecretStore, Error> {
-    let key_tuples = get_key_tuples(keys)
-        .into_iter()
-        .map(|(k, v)| read_file(&v).map(|content| (k, content)))
-        .collect::<Result<Vec<(String, String)>, Error>>()?;
+    let key_tuples = read_key_files(keys)?;
     store.with_sign_keys_from_inline_iter(key_tuples)
 }
 
-pub fn add_verify_keys_from_files(keys: &Keys, store: SecretStore) -> Result<SecretStore, Error> {
-    let key_tuples = get_key_tuples(keys)
+fn read_key_files(keys: &Keys) -> Result<Vec<(String, String)>, Error> {
+    get_key_tuples(keys)
         .into_iter()
         .map(|(k, v)| read_file(&v).map(|content| (k, content)))
-        .collect::<Result<Vec<(String, String)>, Error>>()?;
-    store.with_verify_keys_from_inline_iter(key_tuples)
+        .collect()
 }
 
 fn get_key_tuples(keys: &Keys) -> Vec<(String, String)> {
@@ -101,6 +173,45 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn jwks_from_files() -> Result<(), Error> {
+        let mut cis_settings = CisSettings::default();
+        cis_settings.sign_keys.source = String::from("none");
+        cis_settings.verify_keys.source = String::from("file");
+        cis_settings.verify_keys.ldap_key = Some(String::from("tests/data/fake_key_public.pem"));
+        cis_settings.verify_keys.hris_key = Some(String::from("tests/data/fake_key_public.pem"));
+        let (_, jwks) = get_store_and_jwks_from_settings(&cis_settings).await?;
+        assert_eq!(jwks.keys.len(), 2);
+        assert_eq!(jwks.keys[0].kid, "hris");
+        assert_eq!(jwks.keys[1].kid, "ldap");
+        assert_eq!(jwks.keys[0].e, "AQAB");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn empty_jwks_without_verify_keys() -> Result<(), Error> {
+        let mut cis_settings = CisSettings::default();
+        cis_settings.sign_keys.source = String::from("none");
+        cis_settings.verify_keys.source = String::from("none");
+        let (_, jwks) = get_store_and_jwks_from_settings(&cis_settings).await?;
+        assert!(jwks.keys.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn jwks_skips_invalid_keys() {
+        let key_tuples = vec![
+            (String::from("ldap"), String::from("not a key")),
+            (
+                String::from("hris"),
+                String::from(include_str!("../tests/data/fake_key_public.pem")),
+            ),
+        ];
+        let jwks = jwks_from_pems(&key_tuples);
+        assert_eq!(jwks.keys.len(), 1);
+        assert_eq!(jwks.keys[0].kid, "hris");
+    }
+
     #[test]
     fn test_read_file() -> Result<(), Error> {
         let expected = include_str!("../tests/data/fake_key.json");
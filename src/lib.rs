@@ -11,5 +11,9 @@ pub mod db;
 pub mod error;
 pub mod healthz;
 pub mod keys;
+pub mod notify;
 pub mod profile;
 pub mod settings;
+pub mod status;
+pub mod webhooks;
+pub mod well_known;
@@ -1,3 +1,139 @@
-fn main() {
-    println!("Hello, world!");
+#[macro_use]
+extern crate diesel_migrations;
+#[macro_use]
+extern crate log;
+
+use actix_web::middleware::Logger;
+use actix_web::web;
+use actix_web::App;
+use actix_web::HttpServer;
+use dino_park_cis::api::change::change_app;
+use dino_park_cis::api::events::events_app;
+use dino_park_cis::api::events::stream_app;
+use dino_park_cis::api::person::person_app;
+use dino_park_cis::api::webhooks::webhooks_app;
+use dino_park_cis::db::establish_connection;
+use dino_park_cis::healthz::healthz_app;
+use dino_park_cis::keys::get_store_and_jwks_from_settings;
+use dino_park_cis::notify::listen;
+use dino_park_cis::notify::Broadcaster;
+use dino_park_cis::profile::cis_attributes::IdentifierGenerator;
+use dino_park_cis::profile::publishers::get_rules_store_from_settings;
+use dino_park_cis::profile::validate_rules::validate_rules;
+use dino_park_cis::settings::Settings;
+use dino_park_cis::status::status_app;
+use dino_park_cis::webhooks;
+use dino_park_cis::webhooks::deliver_webhooks;
+use dino_park_cis::well_known::well_known_app;
+use dino_park_gate::provider::Provider;
+use dino_park_gate::scope::ScopeAndUserAuth;
+use failure::Error;
+use std::env;
+use std::fs;
+use std::io;
+use std::process;
+
+embed_migrations!();
+
+fn map_io_err(e: impl Into<Error>) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, e.into().to_string())
+}
+
+/// `validate-rules <file>`: prints the validation report of a publisher rules
+/// document and exits with 1 if it has errors.
+fn validate_rules_cmd(file: Option<&String>) -> io::Result<()> {
+    let file = file.ok_or_else(|| map_io_err(failure::err_msg("usage: validate-rules <file>")))?;
+    let v = serde_json::from_str(&fs::read_to_string(file)?).map_err(map_io_err)?;
+    let report = validate_rules(&v);
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&report).map_err(map_io_err)?
+    );
+    if !report.is_valid() {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(String::as_str) == Some("validate-rules") {
+        return validate_rules_cmd(args.get(2));
+    }
+
+    ::std::env::set_var("RUST_LOG", "info");
+    env_logger::init();
+    info!("starting dino-park-cis");
+
+    let s = Settings::new().map_err(map_io_err)?;
+    let pool = establish_connection(&s.postgres_url);
+    let connection = pool.get().map_err(map_io_err)?;
+    embedded_migrations::run(&connection).map_err(map_io_err)?;
+    drop(connection);
+
+    let (secret_store, jwks) = get_store_and_jwks_from_settings(&s.cis)
+        .await
+        .map_err(map_io_err)?;
+    let secret_store = web::Data::new(secret_store);
+    let jwks = web::Data::new(jwks);
+    let rules = web::Data::new(get_rules_store_from_settings(&s.cis).map_err(map_io_err)?);
+    if s.cis.uuid_salt.is_empty() {
+        return Err(map_io_err(failure::err_msg(
+            "no uuid salt configured (cis.uuid_salt)",
+        )));
+    }
+    let ids = web::Data::new(IdentifierGenerator::new(&s.cis.uuid_salt));
+    let broadcaster = web::Data::new(Broadcaster::default());
+    actix_rt::spawn(listen(s.postgres_url.clone(), broadcaster.clone()));
+    let webhooks_client = webhooks::client().map_err(map_io_err)?;
+    actix_rt::spawn(deliver_webhooks(
+        pool.clone(),
+        webhooks_client,
+        s.webhooks.clone(),
+    ));
+    let provider = Provider::from_issuer(&s.auth).await.map_err(map_io_err)?;
+
+    // actix handles SIGTERM/SIGINT by stopping to accept connections and
+    // waiting up to `shutdown_timeout` seconds for in-flight requests.
+    HttpServer::new(move || {
+        let scope_middleware = || ScopeAndUserAuth::new(provider.clone()).public();
+        App::new()
+            .wrap(Logger::default().exclude("/healthz"))
+            .data(pool.clone())
+            .app_data(secret_store.clone())
+            .app_data(rules.clone())
+            .app_data(jwks.clone())
+            .app_data(ids.clone())
+            .app_data(broadcaster.clone())
+            .service(healthz_app())
+            .service(status_app())
+            .service(well_known_app())
+            .service(
+                web::scope("/cis/api")
+                    .service(
+                        web::scope("/change/v2/events")
+                            .wrap(scope_middleware())
+                            .service(events_app()),
+                    )
+                    .service(
+                        web::scope("/change/v2/stream")
+                            .wrap(scope_middleware())
+                            .service(stream_app()),
+                    )
+                    // Publishers are machines without a user token. Their
+                    // changes are authenticated by the attribute signatures.
+                    .service(change_app())
+                    .service(
+                        web::scope("")
+                            .wrap(scope_middleware())
+                            .service(person_app())
+                            .service(webhooks_app()),
+                    ),
+            )
+    })
+    .shutdown_timeout(s.shutdown_timeout)
+    .bind(&s.bind)?
+    .run()
+    .await
 }
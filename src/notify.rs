@@ -0,0 +1,287 @@
+use crate::db::change::PROFILE_CHANGED;
+use crate::db::model::ProfileEvent;
+use crate::db::types::EventType;
+use actix_web::web;
+use actix_web::web::Bytes;
+use failure::Error;
+use futures::channel::mpsc;
+use futures::stream;
+use futures::StreamExt;
+use log::info;
+use log::warn;
+use openssl::ssl::SslConnector;
+use openssl::ssl::SslMethod;
+use openssl::ssl::SslVerifyMode;
+use postgres_openssl::MakeTlsConnector;
+use serde::Deserialize;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio_postgres::AsyncMessage;
+use url::Url;
+use uuid::Uuid;
+
+const SUBSCRIBER_BUFFER: usize = 64;
+const RECONNECT_SECONDS: u64 = 5;
+
+/// Restricts a stream to one profile and/or to events changing a field path
+/// starting with `field`, e.g. `access_information.mozilliansorg`. Erase
+/// events match every `field` filter.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct StreamFilter {
+    pub uuid: Option<Uuid>,
+    pub field: Option<String>,
+}
+
+impl StreamFilter {
+    pub fn matches(&self, event: &ProfileEvent) -> bool {
+        self.uuid.map(|uuid| uuid == event.uuid).unwrap_or(true)
+            && self
+                .field
+                .as_ref()
+                .map(|prefix| {
+                    event.event == EventType::Erase
+                        || event.fields.iter().any(|f| f.starts_with(prefix.as_str()))
+                })
+                .unwrap_or(true)
+    }
+}
+
+struct Subscriber {
+    filter: StreamFilter,
+    tx: mpsc::Sender<Bytes>,
+}
+
+/// Fans out the events received on the `PROFILE_CHANGED` channel to all
+/// subscribers as server-sent events. Subscribers which do not keep up are
+/// disconnected.
+#[derive(Default)]
+pub struct Broadcaster {
+    subscribers: Mutex<Vec<Subscriber>>,
+    listening: AtomicBool,
+}
+
+impl Broadcaster {
+    pub fn subscribe(&self, filter: StreamFilter) -> mpsc::Receiver<Bytes> {
+        let (tx, rx) = mpsc::channel(SUBSCRIBER_BUFFER);
+        self.subscribers
+            .lock()
+            .unwrap()
+            .push(Subscriber { filter, tx });
+        rx
+    }
+
+    pub fn is_listening(&self) -> bool {
+        self.listening.load(Ordering::SeqCst)
+    }
+
+    pub fn broadcast(&self, event: &ProfileEvent) {
+        let message = match serde_json::to_string(event) {
+            Ok(data) => Bytes::from(format!(
+                "id: {}\nevent: {}\ndata: {}\n\n",
+                event.id, PROFILE_CHANGED, data
+            )),
+            Err(e) => {
+                warn!("unable to serialize event {}: {}", event.id, e);
+                return;
+            }
+        };
+        let mut subscribers = self.subscribers.lock().unwrap();
+        for subscriber in subscribers.iter_mut().filter(|s| s.filter.matches(event)) {
+            if subscriber.tx.try_send(message.clone()).is_err() {
+                subscriber.tx.close_channel();
+            }
+        }
+        subscribers.retain(|s| !s.tx.is_closed());
+    }
+}
+
+/// How the server certificate is checked, following libpq's `sslmode`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SslVerify {
+    None,
+    Ca,
+    Full,
+}
+
+#[derive(Debug, PartialEq)]
+struct TlsOptions {
+    verify: SslVerify,
+    root_cert: Option<String>,
+}
+
+/// Takes the options tokio-postgres does not understand off `postgres_url`:
+/// `sslmode=verify-ca|verify-full` becomes `require` and `sslrootcert` is
+/// dropped. URLs which cannot be parsed are passed on as they are.
+fn tls_options(postgres_url: &str) -> (String, TlsOptions) {
+    let mut options = TlsOptions {
+        verify: SslVerify::None,
+        root_cert: None,
+    };
+    let mut url = match Url::parse(postgres_url) {
+        Ok(url) => url,
+        Err(_) => return (postgres_url.to_owned(), options),
+    };
+    let mut params = vec![];
+    for (k, v) in url.query_pairs() {
+        match (k.as_ref(), v.as_ref()) {
+            ("sslmode", "verify-ca") => options.verify = SslVerify::Ca,
+            ("sslmode", "verify-full") => options.verify = SslVerify::Full,
+            ("sslrootcert", _) => {
+                options.root_cert = Some(v.to_string());
+                continue;
+            }
+            _ => {
+                params.push((k.to_string(), v.to_string()));
+                continue;
+            }
+        }
+        params.push((String::from("sslmode"), String::from("require")));
+    }
+    if params.is_empty() {
+        url.set_query(None);
+    } else {
+        url.query_pairs_mut().clear().extend_pairs(params);
+    }
+    (url.to_string(), options)
+}
+
+/// Like libpq the server certificate is only verified for `verify-ca` and
+/// `verify-full`, against `sslrootcert` or else the system trust store.
+fn tls_connector(options: &TlsOptions) -> Result<MakeTlsConnector, Error> {
+    let mut builder = SslConnector::builder(SslMethod::tls())?;
+    if options.verify == SslVerify::None {
+        builder.set_verify(SslVerifyMode::NONE);
+    }
+    if let Some(root_cert) = &options.root_cert {
+        builder.set_ca_file(root_cert)?;
+    }
+    let mut connector = MakeTlsConnector::new(builder.build());
+    if options.verify != SslVerify::Full {
+        connector.set_callback(|config, _| {
+            config.set_verify_hostname(false);
+            Ok(())
+        });
+    }
+    Ok(connector)
+}
+
+/// Uses TLS whenever the server offers it (or `sslmode` demands it).
+async fn listen_once(postgres_url: &str, broadcaster: &Broadcaster) -> Result<(), Error> {
+    let (postgres_url, options) = tls_options(postgres_url);
+    let tls = tls_connector(&options)?;
+    let (client, mut connection) = tokio_postgres::connect(&postgres_url, tls).await?;
+    let (tx, mut rx) = mpsc::unbounded();
+    let messages = stream::poll_fn(move |cx| connection.poll_message(cx));
+    actix_rt::spawn(async move {
+        if let Err(e) = messages.map(Ok).forward(tx).await {
+            warn!("listener connection closed: {}", e);
+        }
+    });
+    client
+        .batch_execute(&format!("LISTEN {}", PROFILE_CHANGED))
+        .await?;
+    broadcaster.listening.store(true, Ordering::SeqCst);
+    info!("listening on {}", PROFILE_CHANGED);
+    while let Some(message) = rx.next().await {
+        if let AsyncMessage::Notification(n) = message? {
+            match serde_json::from_str::<ProfileEvent>(n.payload()) {
+                Ok(event) => broadcaster.broadcast(&event),
+                Err(e) => warn!("invalid {} payload: {}", PROFILE_CHANGED, e),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Holds a dedicated connection listening on `PROFILE_CHANGED` and reconnects
+/// whenever it is lost.
+pub async fn listen(postgres_url: String, broadcaster: web::Data<Broadcaster>) {
+    loop {
+        if let Err(e) = listen_once(&postgres_url, &broadcaster).await {
+            warn!("listening on {} failed: {}", PROFILE_CHANGED, e);
+        }
+        broadcaster.listening.store(false, Ordering::SeqCst);
+        actix_rt::time::delay_for(Duration::from_secs(RECONNECT_SECONDS)).await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::Utc;
+
+    fn event(event: EventType, fields: &[&str]) -> ProfileEvent {
+        ProfileEvent {
+            id: 1,
+            uuid: Uuid::nil(),
+            version: 1,
+            event,
+            fields: fields.iter().map(|f| f.to_string()).collect(),
+            publishers: vec![String::from("ldap")],
+            created_at: Utc::now().naive_utc(),
+        }
+    }
+
+    #[test]
+    fn test_filter() {
+        let update = event(
+            EventType::Update,
+            &["first_name", "access_information.mozilliansorg"],
+        );
+        assert!(StreamFilter::default().matches(&update));
+
+        let filter = StreamFilter {
+            uuid: None,
+            field: Some(String::from("access_information.mozilliansorg")),
+        };
+        assert!(filter.matches(&update));
+        assert!(!filter.matches(&event(EventType::Update, &["first_name"])));
+        assert!(filter.matches(&event(EventType::Erase, &[])));
+
+        let filter = StreamFilter {
+            uuid: Some(Uuid::new_v4()),
+            field: None,
+        };
+        assert!(!filter.matches(&update));
+    }
+
+    #[test]
+    fn test_tls_options() {
+        let (url, options) = tls_options("postgres://u:p@db/cis?sslmode=prefer");
+        assert_eq!(url, "postgres://u:p@db/cis?sslmode=prefer");
+        assert_eq!(options.verify, SslVerify::None);
+
+        let (url, options) = tls_options(
+            "postgres://u:p@db/cis?sslmode=verify-full&sslrootcert=%2Fca.pem&connect_timeout=5",
+        );
+        assert_eq!(
+            url,
+            "postgres://u:p@db/cis?sslmode=require&connect_timeout=5"
+        );
+        assert_eq!(
+            options,
+            TlsOptions {
+                verify: SslVerify::Full,
+                root_cert: Some(String::from("/ca.pem")),
+            }
+        );
+
+        let (url, options) = tls_options("postgres://u:p@db/cis?sslrootcert=ca.pem");
+        assert_eq!(url, "postgres://u:p@db/cis");
+        assert_eq!(options.verify, SslVerify::None);
+    }
+
+    #[test]
+    fn test_broadcast_drops_closed_subscribers() {
+        let broadcaster = Broadcaster::default();
+        let rx = broadcaster.subscribe(StreamFilter::default());
+        let mut kept = broadcaster.subscribe(StreamFilter::default());
+        drop(rx);
+        broadcaster.broadcast(&event(EventType::Create, &["user_id"]));
+        assert_eq!(broadcaster.subscribers.lock().unwrap().len(), 1);
+        let message = kept.try_next().unwrap().unwrap();
+        assert!(message.starts_with(b"id: 1\nevent: profile_changed\n"));
+    }
+}
@@ -1 +1,224 @@
+use crate::db::change::next_version;
+use crate::db::change::store_profile;
+use crate::db::model::identities_from_profile;
+use crate::db::model::try_from_profile;
+use crate::db::retrieve::identity_conflict;
+use crate::db::retrieve::retrieve_profile_entry_by_user_id;
+use crate::db::Pool;
+use crate::error::DBError;
+use crate::error::ProfileError;
+use crate::error::UpdateError;
+use crate::profile::cis_attributes::sign_cis_attributes;
+use crate::profile::cis_attributes::IdentifierGenerator;
+use crate::profile::publishers::PublisherRules;
+use crate::profile::publishers::PublisherRulesStore;
+use crate::profile::update::update;
+use crate::profile::update::FieldChange;
+use crate::profile::update::Operation;
+use crate::profile::verify::verify_full_profile;
+use cis_profile::crypto::SecretStore;
+use cis_profile::schema::Profile;
+use cis_profile::schema::PublisherAuthority;
+use diesel::pg::PgConnection;
+use failure::Error;
+use log::info;
+use serde::Serialize;
+use uuid::Uuid;
 
+const MAX_ATTEMPTS: usize = 3;
+
+#[derive(Serialize)]
+pub struct ChangeStatus {
+    pub uuid: Uuid,
+    pub version: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub change_id: Option<Uuid>,
+    pub dry_run: bool,
+    pub changes: Vec<FieldChange>,
+}
+
+#[derive(Serialize)]
+pub struct ChangeFailure {
+    pub user_id: Option<String>,
+    pub error: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub publisher: Option<PublisherAuthority>,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum ChangeResult {
+    Ok(ChangeStatus),
+    Err(ChangeFailure),
+}
+
+fn error_code(e: &Error) -> String {
+    if let Some(e) = e.downcast_ref::<UpdateError>() {
+        return e.error.to_string();
+    }
+    if let Some(e) = e.downcast_ref::<ProfileError>() {
+        return e.to_string();
+    }
+    if let Some(e) = e.downcast_ref::<DBError>() {
+        return e.to_string();
+    }
+    ProfileError::UnknownError.to_string()
+}
+
+fn conflicting_field(e: &Error) -> Option<String> {
+    if let Some(DBError::DuplicateValue(field)) = e.downcast_ref::<DBError>() {
+        return Some(field.clone());
+    }
+    match e.downcast_ref::<ProfileError>() {
+        Some(ProfileError::IdentityConflict(identity_type)) => {
+            Some(format!("identities.{}", identity_type))
+        }
+        _ => None,
+    }
+}
+
+/// Verifies, merges and stores a profile update sent by a publisher. The
+/// attributes owned by cis are generated and signed here. With
+/// `dry_run` set, everything but storing the profile is done and the would-be
+/// version is reported. Resubmitting the stored profile changes nothing and
+/// reports the current version. If another writer modifies the profile in between,
+/// the stored profile is re-read and the update merged again.
+pub async fn change_profile(
+    pool: &Pool,
+    store: &SecretStore,
+    rules: &PublisherRulesStore,
+    ids: &IdentifierGenerator,
+    u: Profile,
+    dry_run: bool,
+) -> Result<ChangeStatus, Error> {
+    verify_full_profile(store, &u)?;
+    let user_id = u.user_id.value.clone().ok_or(DBError::InvalidProfile)?;
+    let rules = rules.get().await?;
+    let connection = pool.get()?;
+    let mut attempt = 1;
+    loop {
+        match merge_and_store(
+            &connection,
+            store,
+            &rules,
+            ids,
+            &user_id,
+            u.clone(),
+            dry_run,
+        ) {
+            Err(ref e)
+                if attempt < MAX_ATTEMPTS
+                    && e.downcast_ref::<DBError>() == Some(&DBError::ConcurrentModification) =>
+            {
+                info!(
+                    "concurrent modification of {} (attempt {})",
+                    user_id, attempt
+                );
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Rejects `p` if one of its linked identities belongs to another profile.
+/// The unique index on `profile_identities` catches concurrent writers.
+fn check_identities(connection: &PgConnection, p: &Profile) -> Result<(), Error> {
+    let uuid = p.uuid.value.as_deref().ok_or(DBError::InvalidProfile)?;
+    let uuid = Uuid::parse_str(uuid)?;
+    match identity_conflict(connection, uuid, &identities_from_profile(p))? {
+        Some(identity_type) => {
+            Err(ProfileError::IdentityConflict(String::from(identity_type)).into())
+        }
+        None => Ok(()),
+    }
+}
+
+/// Whether `changes` leave the stored profile as it is. `last_modified` is
+/// maintained by cis and does not count as a change.
+fn unchanged(changes: &[FieldChange]) -> bool {
+    changes
+        .iter()
+        .all(|c| c.operation == Operation::Noop || c.field == "last_modified")
+}
+
+fn merge_and_store(
+    connection: &PgConnection,
+    store: &SecretStore,
+    rules: &PublisherRules,
+    ids: &IdentifierGenerator,
+    user_id: &str,
+    u: Profile,
+    dry_run: bool,
+) -> Result<ChangeStatus, Error> {
+    let (p, version) = match retrieve_profile_entry_by_user_id(connection, user_id)? {
+        Some(pe) => (serde_json::from_value(pe.profile)?, pe.version),
+        None => (Profile::default(), 0),
+    };
+    let mut updated = update(p, u, rules)?;
+    if version > 0 && unchanged(&updated.changes) {
+        let pe = try_from_profile(updated.profile, version)?;
+        return Ok(ChangeStatus {
+            uuid: pe.uuid,
+            version,
+            change_id: None,
+            dry_run,
+            changes: updated.changes,
+        });
+    }
+    sign_cis_attributes(connection, store, ids, &mut updated)?;
+    check_identities(connection, &updated.profile)?;
+    if dry_run {
+        let pe = try_from_profile(updated.profile, next_version(version))?;
+        return Ok(ChangeStatus {
+            uuid: pe.uuid,
+            version: pe.version,
+            change_id: None,
+            dry_run,
+            changes: updated.changes,
+        });
+    }
+    let pe = store_profile(connection, updated.profile, version, &updated.changes)?;
+    Ok(ChangeStatus {
+        uuid: pe.uuid,
+        version: pe.version,
+        change_id: Some(Uuid::new_v4()),
+        dry_run,
+        changes: updated.changes,
+    })
+}
+
+/// Runs every profile through [`change_profile`] one after another, each in its
+/// own transaction. A failing profile is reported in its slot and does not
+/// affect the others.
+pub async fn change_profiles(
+    pool: &Pool,
+    store: &SecretStore,
+    rules: &PublisherRulesStore,
+    ids: &IdentifierGenerator,
+    us: Vec<Profile>,
+    dry_run: bool,
+) -> Vec<ChangeResult> {
+    let mut results = Vec::with_capacity(us.len());
+    for u in us {
+        let user_id = u.user_id.value.clone();
+        let result = match change_profile(pool, store, rules, ids, u, dry_run).await {
+            Ok(status) => ChangeResult::Ok(status),
+            Err(e) => {
+                let rejected = e.downcast_ref::<UpdateError>();
+                ChangeResult::Err(ChangeFailure {
+                    user_id,
+                    error: error_code(&e),
+                    field: rejected
+                        .map(|r| r.field.clone())
+                        .or_else(|| conflicting_field(&e)),
+                    publisher: rejected.map(|r| r.publisher.clone()),
+                })
+            }
+        };
+        results.push(result);
+    }
+    results
+}
@@ -0,0 +1,174 @@
+use crate::db::retrieve::primary_username_taken;
+use crate::db::retrieve::uuid_taken;
+use crate::error::DBError;
+use crate::error::ProfileError;
+use crate::profile::update::FieldChange;
+use crate::profile::update::Operation;
+use crate::profile::update::Updated;
+use chrono::DateTime;
+use chrono::SecondsFormat;
+use chrono::Utc;
+use cis_profile::crypto::SecretStore;
+use cis_profile::crypto::Signer;
+use cis_profile::schema::Display;
+use cis_profile::schema::PublisherAuthority;
+use cis_profile::schema::StandardAttributeString;
+use diesel::pg::PgConnection;
+use failure::Error;
+use log::warn;
+use serde_json::Value;
+use uuid::Uuid;
+
+const MAX_GENERATE_ATTEMPTS: usize = 8;
+
+/// Derives uuids and primary usernames for new profiles. The uuid is a
+/// name-based (v5) uuid of the `user_id` in a namespace derived from the
+/// configured salt, so it is stable for a given `user_id`.
+pub struct IdentifierGenerator {
+    namespace: Uuid,
+}
+
+impl IdentifierGenerator {
+    pub fn new(salt: &str) -> Self {
+        IdentifierGenerator {
+            namespace: Uuid::new_v5(&Uuid::NAMESPACE_OID, salt.as_bytes()),
+        }
+    }
+
+    /// The uuid for `user_id`. On the (unlikely) event of a collision with
+    /// another profile the `user_id` is re-hashed with a counter.
+    pub fn uuid(&self, connection: &PgConnection, user_id: &str) -> Result<Uuid, Error> {
+        for attempt in 0..MAX_GENERATE_ATTEMPTS {
+            let name = match attempt {
+                0 => user_id.to_owned(),
+                n => format!("{}#{}", user_id, n),
+            };
+            let uuid = Uuid::new_v5(&self.namespace, name.as_bytes());
+            if !uuid_taken(connection, uuid)? {
+                return Ok(uuid);
+            }
+            warn!("uuid collision for {} (attempt {})", user_id, attempt);
+        }
+        Err(ProfileError::UnknownError.into())
+    }
+
+    /// A random `r--` primary username which is not taken yet. Taken names
+    /// are regenerated.
+    pub fn primary_username(&self, connection: &PgConnection) -> Result<String, Error> {
+        for _ in 0..MAX_GENERATE_ATTEMPTS {
+            let username = format!("r--{}", Uuid::new_v4().to_simple());
+            if !primary_username_taken(connection, &username)? {
+                return Ok(username);
+            }
+            warn!("primary username collision for {}", username);
+        }
+        Err(ProfileError::UnknownError.into())
+    }
+}
+
+fn set_cis_attribute(
+    field: &str,
+    attr: &mut StandardAttributeString,
+    value: String,
+    display: Display,
+    now: DateTime<Utc>,
+    changes: &mut Vec<FieldChange>,
+) {
+    let operation = if attr.value.is_none() {
+        attr.metadata.created = now;
+        Operation::Create
+    } else {
+        Operation::Update
+    };
+    changes.retain(|c| c.field != field);
+    changes.push(FieldChange {
+        field: field.to_owned(),
+        operation,
+        publisher: PublisherAuthority::Cis,
+        old: serde_json::to_value(&attr.value).unwrap_or_default(),
+        new: Value::from(value.clone()),
+        old_display: attr.metadata.display.clone(),
+        new_display: attr.metadata.display.clone().or(Some(display.clone())),
+    });
+    attr.value = Some(value);
+    attr.metadata.last_modified = now;
+    if attr.metadata.display.is_none() {
+        attr.metadata.display = Some(display);
+    }
+    attr.signature.publisher.name = PublisherAuthority::Cis;
+}
+
+/// Fills in the attributes owned by the `cis` publisher: `uuid`,
+/// `primary_username` and `created` when missing and `last_modified` whenever
+/// anything changed. All of them are (re-)signed with the cis key.
+pub fn sign_cis_attributes(
+    connection: &PgConnection,
+    store: &SecretStore,
+    ids: &IdentifierGenerator,
+    updated: &mut Updated,
+) -> Result<(), Error> {
+    let now = Utc::now();
+    let timestamp = now.to_rfc3339_opts(SecondsFormat::Millis, true);
+    let changed = updated
+        .changes
+        .iter()
+        .any(|c| c.operation != Operation::Noop);
+    let p = &mut updated.profile;
+    let changes = &mut updated.changes;
+
+    if p.uuid.value.is_none() {
+        let user_id = p.user_id.value.as_deref().ok_or(DBError::InvalidProfile)?;
+        let uuid = ids.uuid(connection, user_id)?.to_hyphenated().to_string();
+        set_cis_attribute("uuid", &mut p.uuid, uuid, Display::Public, now, changes);
+    }
+    if p.primary_username.value.is_none() {
+        let username = ids.primary_username(connection)?;
+        set_cis_attribute(
+            "primary_username",
+            &mut p.primary_username,
+            username,
+            Display::Public,
+            now,
+            changes,
+        );
+    }
+    if p.created.value.is_none() {
+        set_cis_attribute(
+            "created",
+            &mut p.created,
+            timestamp.clone(),
+            Display::Staff,
+            now,
+            changes,
+        );
+    }
+    if changed || p.last_modified.value.is_none() {
+        set_cis_attribute(
+            "last_modified",
+            &mut p.last_modified,
+            timestamp,
+            Display::Staff,
+            now,
+            changes,
+        );
+    }
+
+    store.sign_attribute(&mut p.uuid)?;
+    store.sign_attribute(&mut p.primary_username)?;
+    store.sign_attribute(&mut p.created)?;
+    store.sign_attribute(&mut p.last_modified)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_uuid_namespace_depends_on_salt() {
+        let a = IdentifierGenerator::new("a");
+        let b = IdentifierGenerator::new("b");
+        assert_ne!(a.namespace, b.namespace);
+        assert_eq!(a.namespace, IdentifierGenerator::new("a").namespace);
+    }
+}
@@ -0,0 +1,112 @@
+use crate::db::change::deactivate_profile;
+use crate::db::change::erase_profile;
+use crate::db::model::ProfileTombstone;
+use crate::db::retrieve::retrieve_profile_entry;
+use crate::db::types::DeletionType;
+use crate::db::Pool;
+use crate::error::DBError;
+use crate::error::ProfileError;
+use crate::profile::cis_attributes::sign_cis_attributes;
+use crate::profile::cis_attributes::IdentifierGenerator;
+use crate::profile::publishers::publisher_name;
+use crate::profile::update::FieldChange;
+use crate::profile::update::Operation;
+use crate::profile::update::Updated;
+use crate::profile::verify::verify_full_profile;
+use cis_profile::crypto::SecretStore;
+use cis_profile::crypto::Signer;
+use cis_profile::schema::Profile;
+use cis_profile::schema::PublisherAuthority;
+use failure::Error;
+use serde_json::Value;
+use uuid::Uuid;
+
+/// Publishers which may deactivate a profile.
+const ALLOWED_DELETERS: [PublisherAuthority; 3] = [
+    PublisherAuthority::Hris,
+    PublisherAuthority::Ldap,
+    PublisherAuthority::Cis,
+];
+
+/// Publishers which may erase a profile. Erasing cannot be undone.
+const ALLOWED_ERASERS: [PublisherAuthority; 1] = [PublisherAuthority::Cis];
+
+/// Everything but the identifiers and the attributes owned by cis.
+fn strip(p: Profile) -> Profile {
+    Profile {
+        uuid: p.uuid,
+        user_id: p.user_id,
+        primary_email: p.primary_email,
+        primary_username: p.primary_username,
+        login_method: p.login_method,
+        active: p.active,
+        created: p.created,
+        last_modified: p.last_modified,
+        ..Default::default()
+    }
+}
+
+/// Deletes the profile `uuid`. The request `u` must carry the `user_id` of
+/// the profile and `active` set to `false`, signed by one of the
+/// `ALLOWED_DELETERS`, or the `ALLOWED_ERASERS` for a hard deletion. A soft
+/// deletion deactivates the profile and strips all non-essential attributes,
+/// a hard deletion erases it including its history.
+/// Without an explicit `version` the currently stored version is deleted.
+pub async fn delete_profile(
+    pool: &Pool,
+    store: &SecretStore,
+    ids: &IdentifierGenerator,
+    uuid: Uuid,
+    u: Profile,
+    deletion: DeletionType,
+    version: Option<i32>,
+) -> Result<ProfileTombstone, Error> {
+    verify_full_profile(store, &u)?;
+    if u.active.value != Some(false) || u.user_id.value.is_none() {
+        return Err(DBError::InvalidProfile.into());
+    }
+    let publisher = u.active.signature.publisher.name.clone();
+    let allowed = match deletion {
+        DeletionType::Soft => &ALLOWED_DELETERS[..],
+        DeletionType::Hard => &ALLOWED_ERASERS[..],
+    };
+    if !allowed.contains(&publisher) {
+        return Err(ProfileError::PublisherNotAllowedToDelete.into());
+    }
+    let connection = pool.get()?;
+    let pe = retrieve_profile_entry(&connection, uuid)?.ok_or(DBError::NotFound)?;
+    if u.user_id.value.as_ref() != Some(&pe.user_id) {
+        return Err(DBError::InvalidProfile.into());
+    }
+    let version = version.unwrap_or(pe.version);
+    match deletion {
+        DeletionType::Hard => erase_profile(&connection, uuid, version, publisher_name(&publisher)),
+        DeletionType::Soft => {
+            let mut p = strip(serde_json::from_value(pe.profile)?);
+            let change = FieldChange {
+                field: String::from("active"),
+                operation: Operation::Update,
+                publisher: publisher.clone(),
+                old: serde_json::to_value(&p.active.value).unwrap_or_default(),
+                new: Value::from(false),
+                old_display: p.active.metadata.display.clone(),
+                new_display: p.active.metadata.display.clone(),
+            };
+            p.active.value = Some(false);
+            p.active.signature.publisher.name = PublisherAuthority::Cis;
+            store.sign_attribute(&mut p.active)?;
+            let mut updated = Updated {
+                profile: p,
+                changes: vec![change],
+            };
+            sign_cis_attributes(&connection, store, ids, &mut updated)?;
+            deactivate_profile(
+                &connection,
+                updated.profile,
+                version,
+                &updated.changes,
+                publisher_name(&publisher),
+            )
+        }
+    }
+}
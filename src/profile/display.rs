@@ -1,12 +1,25 @@
+use crate::db::model::ProfileChangeEntry;
+use crate::db::types::TrustType;
+use cis_profile::schema::Display;
+use cis_profile::schema::Profile;
 use serde::Deserialize;
+use serde_json::Value;
+use std::convert::TryFrom;
 
 #[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum DisplayFilter {
     True,
     False,
     Any,
 }
 
+impl Default for DisplayFilter {
+    fn default() -> Self {
+        Self::Any
+    }
+}
+
 impl DisplayFilter {
     pub fn filter(&self) -> &[bool] {
         match self {
@@ -16,3 +29,162 @@ impl DisplayFilter {
         }
     }
 }
+
+fn visible(display: &Option<Display>, scope: &TrustType) -> bool {
+    display
+        .clone()
+        .and_then(|d| TrustType::try_from(d).ok())
+        .map(|level| level <= *scope)
+        .unwrap_or_default()
+}
+
+macro_rules! scrub {
+    ($($f:ident).*, $p:ident, $s:ident, $v:ident) => {
+        if !visible(&$p.$($f).*.metadata.display, $s) {
+            $p.$($f).*.$v = None;
+        }
+    };
+}
+
+/// Nulls out every attribute whose display level is above `scope`.
+/// Attributes without (or with an unknown) display level are never shown.
+pub fn scrub_profile(mut p: Profile, scope: &TrustType) -> Profile {
+    scrub!(uuid, p, scope, value);
+    scrub!(user_id, p, scope, value);
+    scrub!(primary_username, p, scope, value);
+    scrub!(login_method, p, scope, value);
+    scrub!(active, p, scope, value);
+    scrub!(last_modified, p, scope, value);
+    scrub!(created, p, scope, value);
+    scrub!(usernames, p, scope, values);
+    scrub!(pronouns, p, scope, value);
+    scrub!(first_name, p, scope, value);
+    scrub!(last_name, p, scope, value);
+    scrub!(alternative_name, p, scope, value);
+    scrub!(primary_email, p, scope, value);
+    scrub!(ssh_public_keys, p, scope, values);
+    scrub!(pgp_public_keys, p, scope, values);
+    scrub!(fun_title, p, scope, value);
+    scrub!(description, p, scope, value);
+    scrub!(location, p, scope, value);
+    scrub!(timezone, p, scope, value);
+    scrub!(languages, p, scope, values);
+    scrub!(tags, p, scope, values);
+    scrub!(picture, p, scope, value);
+    scrub!(uris, p, scope, values);
+    scrub!(phone_numbers, p, scope, values);
+
+    scrub!(identities.github_id_v3, p, scope, value);
+    scrub!(identities.github_id_v4, p, scope, value);
+    scrub!(identities.github_primary_email, p, scope, value);
+    scrub!(identities.mozilliansorg_id, p, scope, value);
+    scrub!(identities.bugzilla_mozilla_org_id, p, scope, value);
+    scrub!(
+        identities.bugzilla_mozilla_org_primary_email,
+        p,
+        scope,
+        value
+    );
+    scrub!(identities.mozilla_ldap_id, p, scope, value);
+    scrub!(identities.mozilla_ldap_primary_email, p, scope, value);
+    scrub!(identities.mozilla_posix_id, p, scope, value);
+    scrub!(identities.google_oauth2_id, p, scope, value);
+    scrub!(identities.google_primary_email, p, scope, value);
+    scrub!(identities.firefox_accounts_id, p, scope, value);
+    scrub!(identities.firefox_accounts_primary_email, p, scope, value);
+    scrub!(identities.custom_1_primary_email, p, scope, value);
+    scrub!(identities.custom_2_primary_email, p, scope, value);
+    scrub!(identities.custom_3_primary_email, p, scope, value);
+
+    scrub!(access_information.access_provider, p, scope, values);
+    scrub!(access_information.ldap, p, scope, values);
+    scrub!(access_information.hris, p, scope, values);
+    scrub!(access_information.mozilliansorg, p, scope, values);
+
+    scrub!(staff_information.manager, p, scope, value);
+    scrub!(staff_information.director, p, scope, value);
+    scrub!(staff_information.staff, p, scope, value);
+    scrub!(staff_information.title, p, scope, value);
+    scrub!(staff_information.team, p, scope, value);
+    scrub!(staff_information.cost_center, p, scope, value);
+    scrub!(staff_information.worker_type, p, scope, value);
+    scrub!(staff_information.wpr_desk_number, p, scope, value);
+    scrub!(staff_information.office_location, p, scope, value);
+    p
+}
+
+fn visible_level(level: &Option<TrustType>, scope: &TrustType) -> bool {
+    level.as_ref().map(|l| l <= scope).unwrap_or_default()
+}
+
+/// Nulls out the old and new value of an audit log entry unless its display
+/// level at the time of the change is within `scope`.
+pub fn scrub_change(mut c: ProfileChangeEntry, scope: &TrustType) -> ProfileChangeEntry {
+    if !visible_level(&c.old_display, scope) {
+        c.old_value = Value::Null;
+    }
+    if !visible_level(&c.new_display, scope) {
+        c.new_value = Value::Null;
+    }
+    c
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::types::OperationType;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_scrub() {
+        let mut p = Profile::default();
+        p.first_name.value = Some(String::from("dino"));
+        p.first_name.metadata.display = Some(Display::Public);
+        p.last_name.value = Some(String::from("saur"));
+        p.last_name.metadata.display = Some(Display::Staff);
+        p.pronouns.value = Some(String::from("dino"));
+        p.pronouns.metadata.display = None;
+        p.staff_information.title.value = Some(String::from("dino"));
+        p.staff_information.title.metadata.display = Some(Display::Ndaed);
+
+        let public = scrub_profile(p.clone(), &TrustType::Public);
+        assert_eq!(public.first_name.value, Some(String::from("dino")));
+        assert_eq!(public.last_name.value, None);
+        assert_eq!(public.pronouns.value, None);
+        assert_eq!(public.staff_information.title.value, None);
+
+        let ndaed = scrub_profile(p.clone(), &TrustType::Ndaed);
+        assert_eq!(ndaed.last_name.value, None);
+        assert_eq!(
+            ndaed.staff_information.title.value,
+            Some(String::from("dino"))
+        );
+
+        let staff = scrub_profile(p, &TrustType::Staff);
+        assert_eq!(staff.last_name.value, Some(String::from("saur")));
+    }
+
+    #[test]
+    fn test_scrub_change() {
+        let c = ProfileChangeEntry {
+            id: 1,
+            uuid: Uuid::nil(),
+            version: 2,
+            field: String::from("last_name"),
+            operation: OperationType::Update,
+            publisher: String::from("mozilliansorg"),
+            old_value: Value::from("saur"),
+            new_value: Value::from("dino"),
+            old_display: Some(TrustType::Staff),
+            new_display: None,
+            changed_at: chrono::Utc::now().naive_utc(),
+        };
+        let ndaed = scrub_change(c.clone(), &TrustType::Ndaed);
+        assert_eq!(ndaed.old_value, Value::Null);
+        assert_eq!(ndaed.new_value, Value::Null);
+
+        let staff = scrub_change(c, &TrustType::Staff);
+        assert_eq!(staff.old_value, Value::from("saur"));
+        assert_eq!(staff.new_value, Value::Null);
+    }
+}
@@ -1,4 +1,8 @@
 pub mod change;
+pub mod cis_attributes;
+pub mod delete;
 pub mod display;
 pub mod publishers;
 pub mod update;
+pub mod validate_rules;
+pub mod verify;
@@ -1,33 +1,217 @@
+use crate::error::RulesError;
+use crate::profile::validate_rules::rules_from_str;
+use crate::profile::validate_rules::rules_from_value;
+use crate::settings::CisSettings;
 use chrono::DateTime;
 use chrono::Duration;
 use chrono::Utc;
 use cis_profile::schema::PublisherAuthority;
 use failure::Error;
+use futures::future;
 use futures::FutureExt;
 use futures::TryFutureExt;
 use headers::CacheControl;
 use headers::HeaderMapExt;
-use lazy_static::lazy_static;
+use log::warn;
 use serde::de;
 use serde::de::MapAccess;
 use serde::de::Visitor;
 use serde::Deserialize;
 use serde::Deserializer;
 use serde::Serialize;
+use serde_json::Value;
 use shared_expiry_get::Expiry;
 use shared_expiry_get::ExpiryFut;
 use shared_expiry_get::ExpiryGetError;
 use shared_expiry_get::Provider;
 use shared_expiry_get::RemoteStore;
 use std::fmt;
+use std::fs;
 use std::marker::PhantomData;
 use std::str::FromStr;
+use std::sync::RwLock;
+use std::time;
 
-lazy_static! {
-    pub static ref PUBLISHER_RULES: RemoteStore<RemotePublisherRules, RemotePublisherRulesProvider> =
-        RemoteStore::new(RemotePublisherRulesProvider {
-            url: String::from("https://auth.mozilla.com/.well-known/mozilla-iam-publisher-rules"),
+const DEFAULT_MAX_AGE_HOURS: i64 = 24;
+const FETCH_TIMEOUT_SECONDS: u64 = 10;
+const RETRY_AFTER_SECONDS: i64 = 60;
+
+pub enum PublisherRulesStore {
+    Remote(FallbackRulesStore),
+    Static(PublisherRules),
+}
+
+impl PublisherRulesStore {
+    pub async fn get(&self) -> Result<PublisherRules, Error> {
+        self.current().await.map(|current| current.rules)
+    }
+
+    /// The current rules and until when they are considered valid. Static
+    /// rules are valid for the same default period remote rules get without
+    /// a `Cache-Control` header.
+    pub async fn current(&self) -> Result<RemotePublisherRules, Error> {
+        match self {
+            Self::Remote(store) => store.get().await,
+            Self::Static(rules) => Ok(RemotePublisherRules {
+                rules: rules.clone(),
+                valid_till: Utc::now() + Duration::hours(DEFAULT_MAX_AGE_HOURS),
+                fetched_at: None,
+            }),
+        }
+    }
+
+    pub fn status(&self) -> RulesStatus {
+        match self {
+            Self::Remote(store) => store.status(),
+            Self::Static(_) => RulesStatus {
+                source: "static",
+                loaded: true,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+#[derive(Default)]
+struct FallbackState {
+    last_good: Option<RemotePublisherRules>,
+    last_error: Option<String>,
+    last_error_at: Option<DateTime<Utc>>,
+    retry_after: Option<DateTime<Utc>>,
+}
+
+/// Remote publisher rules which keeps serving the last successfully fetched
+/// (or seeded) rules past `valid_till` while the remote is unavailable. After
+/// a failed fetch the next attempt is delayed by `RETRY_AFTER_SECONDS`.
+pub struct FallbackRulesStore {
+    store: RemoteStore<RemotePublisherRules, RemotePublisherRulesProvider>,
+    state: RwLock<FallbackState>,
+}
+
+impl FallbackRulesStore {
+    pub fn new(provider: RemotePublisherRulesProvider, seed: Option<PublisherRules>) -> Self {
+        let last_good = seed.map(|rules| RemotePublisherRules {
+            rules,
+            valid_till: Utc::now(),
+            fetched_at: None,
         });
+        FallbackRulesStore {
+            store: RemoteStore::new(provider),
+            state: RwLock::new(FallbackState {
+                last_good,
+                ..Default::default()
+            }),
+        }
+    }
+
+    pub async fn get(&self) -> Result<RemotePublisherRules, Error> {
+        if let Some(last_good) = self.backing_off() {
+            return Ok(last_good);
+        }
+        match self.store.get().await {
+            Ok(rules) => {
+                let mut state = self.state.write().unwrap();
+                state.last_good = Some(rules.clone());
+                state.retry_after = None;
+                Ok(rules)
+            }
+            Err(e) => {
+                let e = format!("{:?}", e);
+                warn!("failed to fetch publisher rules: {}", e);
+                let now = Utc::now();
+                let mut state = self.state.write().unwrap();
+                state.last_error = Some(e.clone());
+                state.last_error_at = Some(now);
+                state.retry_after = Some(now + Duration::seconds(RETRY_AFTER_SECONDS));
+                state
+                    .last_good
+                    .clone()
+                    .ok_or_else(|| RulesError::Unavailable(e).into())
+            }
+        }
+    }
+
+    fn backing_off(&self) -> Option<RemotePublisherRules> {
+        let state = self.state.read().unwrap();
+        match state.retry_after {
+            Some(retry_after) if Utc::now() < retry_after => state.last_good.clone(),
+            _ => None,
+        }
+    }
+
+    pub fn status(&self) -> RulesStatus {
+        let state = self.state.read().unwrap();
+        let now = Utc::now();
+        let last_good = state.last_good.as_ref();
+        let fetched_at = last_good.and_then(|r| r.fetched_at);
+        RulesStatus {
+            source: "remote",
+            loaded: last_good.is_some(),
+            fetched_at,
+            age_seconds: fetched_at.map(|t| (now - t).num_seconds()),
+            valid_till: last_good.map(|r| r.valid_till),
+            stale: last_good.map(|r| !r.valid()).unwrap_or_default(),
+            last_error: state.last_error.clone(),
+            last_error_at: state.last_error_at,
+        }
+    }
+}
+
+/// Where the publisher rules come from and how fresh they are. Seeded rules
+/// are loaded but have no `fetched_at`.
+#[derive(Debug, Default, Serialize)]
+pub struct RulesStatus {
+    pub source: &'static str,
+    pub loaded: bool,
+    pub fetched_at: Option<DateTime<Utc>>,
+    pub age_seconds: Option<i64>,
+    pub valid_till: Option<DateTime<Utc>>,
+    pub stale: bool,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<DateTime<Utc>>,
+}
+
+/// The part of [`RulesStatus`] which is safe to expose without
+/// authentication. Fetch errors may reveal internal details.
+#[derive(Debug, Serialize)]
+pub struct PublicRulesStatus {
+    pub stale: bool,
+    pub valid_till: Option<DateTime<Utc>>,
+}
+
+impl From<RulesStatus> for PublicRulesStatus {
+    fn from(status: RulesStatus) -> Self {
+        PublicRulesStatus {
+            stale: status.stale,
+            valid_till: status.valid_till,
+        }
+    }
+}
+
+pub fn get_rules_store_from_settings(settings: &CisSettings) -> Result<PublisherRulesStore, Error> {
+    let rules = &settings.publisher_rules;
+    match (
+        rules.source.as_str(),
+        &rules.url,
+        &rules.file,
+        &rules.inline,
+    ) {
+        ("remote", Some(url), _, _) => {
+            let seed = match &rules.fallback_file {
+                Some(file) => Some(rules_from_str(&fs::read_to_string(file)?)?),
+                None => None,
+            };
+            Ok(PublisherRulesStore::Remote(FallbackRulesStore::new(
+                RemotePublisherRulesProvider::new(url)?,
+                seed,
+            )))
+        }
+        ("file", _, Some(file), _) => Ok(PublisherRulesStore::Static(rules_from_str(
+            &fs::read_to_string(file)?,
+        )?)),
+        ("inline", _, _, Some(inline)) => Ok(PublisherRulesStore::Static(rules_from_str(inline)?)),
+        _ => Err(RulesError::UseRemoteFileInline.into()),
+    }
 }
 
 #[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
@@ -75,6 +259,14 @@ impl PartialEq<PublisherAuthority> for Publisher {
     }
 }
 
+/// The name of a publisher as used in signatures and publisher rules.
+pub fn publisher_name(p: &PublisherAuthority) -> String {
+    serde_json::to_value(p)
+        .ok()
+        .and_then(|v| v.as_str().map(String::from))
+        .unwrap_or_default()
+}
+
 impl From<Publisher> for Vec<PublisherAuthority> {
     fn from(p: Publisher) -> Self {
         match p {
@@ -260,7 +452,7 @@ where
         where
             E: de::Error,
         {
-            Ok(FromStr::from_str(value).unwrap())
+            FromStr::from_str(value).map_err(de::Error::custom)
         }
 
         fn visit_map<M>(self, map: M) -> Result<T, M::Error>
@@ -277,14 +469,28 @@ where
 
     deserializer.deserialize_any(StringOrStruct(PhantomData))
 }
+
 pub struct RemotePublisherRulesProvider {
-    url: String,
+    url: reqwest::Url,
+    client: reqwest::Client,
+}
+
+impl RemotePublisherRulesProvider {
+    pub fn new(url: &str) -> Result<Self, Error> {
+        Ok(RemotePublisherRulesProvider {
+            url: reqwest::Url::parse(url)?,
+            client: reqwest::Client::builder()
+                .timeout(time::Duration::from_secs(FETCH_TIMEOUT_SECONDS))
+                .build()?,
+        })
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct RemotePublisherRules {
     pub rules: PublisherRules,
     pub valid_till: DateTime<Utc>,
+    pub fetched_at: Option<DateTime<Utc>>,
 }
 
 impl Expiry for RemotePublisherRules {
@@ -295,25 +501,35 @@ impl Expiry for RemotePublisherRules {
 
 impl Provider<RemotePublisherRules> for RemotePublisherRulesProvider {
     fn update(&self) -> ExpiryFut<RemotePublisherRules> {
-        reqwest::get(reqwest::Url::parse(&self.url).unwrap())
+        self.client
+            .get(self.url.clone())
+            .send()
             .map_ok(move |res| {
                 let headers = res.headers();
                 let cc: Option<CacheControl> = headers.typed_get();
                 let max_age = cc
                     .and_then(|cc| cc.max_age())
                     .and_then(|max_age| Duration::from_std(max_age).ok())
-                    .unwrap_or_else(|| Duration::hours(24));
+                    .unwrap_or_else(|| Duration::hours(DEFAULT_MAX_AGE_HOURS));
 
                 (res, max_age)
             })
-            .and_then(move |(res, max_age)| {
-                res.json::<PublisherRules>()
-                    .map_ok(move |rules| RemotePublisherRules {
-                        rules,
-                        valid_till: Utc::now() + max_age,
-                    })
-            })
+            .and_then(move |(res, max_age)| res.json::<Value>().map_ok(move |v| (v, max_age)))
             .map_err(|e| ExpiryGetError::UpdateFailed(e.to_string()))
+            .and_then(move |(v, max_age)| {
+                future::ready(
+                    rules_from_value(v)
+                        .map(|rules| {
+                            let now = Utc::now();
+                            RemotePublisherRules {
+                                rules,
+                                valid_till: now + max_age,
+                                fetched_at: Some(now),
+                            }
+                        })
+                        .map_err(|e| ExpiryGetError::UpdateFailed(e.to_string())),
+                )
+            })
             .boxed()
     }
 }
@@ -328,4 +544,60 @@ mod test {
             serde_json::from_str::<PublisherRules>(include_str!("../../tests/data/rules.json"));
         assert!(rules.is_ok());
     }
+
+    #[tokio::test]
+    async fn rules_store_from_settings() -> Result<(), Error> {
+        let mut cis_settings = CisSettings::default();
+        assert!(get_rules_store_from_settings(&cis_settings).is_err());
+
+        cis_settings.publisher_rules.source = String::from("file");
+        cis_settings.publisher_rules.file = Some(String::from("tests/data/rules.json"));
+        let rules = get_rules_store_from_settings(&cis_settings)?.get().await?;
+        assert!(rules.create.uuid.check(&PublisherAuthority::Cis));
+
+        cis_settings.publisher_rules.source = String::from("inline");
+        cis_settings.publisher_rules.inline =
+            Some(String::from(include_str!("../../tests/data/rules.json")));
+        let rules = get_rules_store_from_settings(&cis_settings)?.get().await?;
+        assert!(!rules.update.uuid.check(&PublisherAuthority::Cis));
+
+        cis_settings.publisher_rules.inline = Some(String::from(r#"{"create": "ldap"}"#));
+        assert!(get_rules_store_from_settings(&cis_settings).is_err());
+
+        cis_settings.publisher_rules.source = String::from("remote");
+        cis_settings.publisher_rules.url = Some(String::from("not a url"));
+        assert!(get_rules_store_from_settings(&cis_settings).is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn fallback_to_seed_when_remote_fails() -> Result<(), Error> {
+        let provider = RemotePublisherRulesProvider::new("http://127.0.0.1:1/rules")?;
+        let store = FallbackRulesStore::new(provider, None);
+        assert!(store.get().await.is_err());
+        assert!(!store.status().loaded);
+        assert!(store.status().last_error.is_some());
+
+        let provider = RemotePublisherRulesProvider::new("http://127.0.0.1:1/rules")?;
+        let seed = rules_from_str(include_str!("../../tests/data/rules.json"))?;
+        let store = FallbackRulesStore::new(provider, Some(seed));
+        let rules = store.get().await?;
+        assert!(rules.rules.create.uuid.check(&PublisherAuthority::Cis));
+        let status = store.status();
+        assert!(status.loaded);
+        assert!(status.stale);
+        assert!(status.fetched_at.is_none());
+        assert!(status.last_error.is_some());
+        // Backing off serves the seed without fetching again.
+        assert!(store.get().await.is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_group_string_does_not_panic() {
+        let mut v: Value =
+            serde_json::from_str(include_str!("../../tests/data/rules.json")).unwrap();
+        v["update"]["identities"] = Value::from("nobody");
+        assert!(serde_json::from_value::<PublisherRules>(v).is_err());
+    }
 }
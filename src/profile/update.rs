@@ -1,20 +1,50 @@
 use crate::error::ProfileError;
-use crate::profile::publishers::PUBLISHER_RULES;
+use crate::error::UpdateError;
+use crate::profile::publishers::PublisherRules;
 use cis_profile::schema::AccessInformationProviderSubObject;
+use cis_profile::schema::Display;
 use cis_profile::schema::Profile;
 use cis_profile::schema::PublisherAuthority;
 use cis_profile::schema::StandardAttributeBoolean;
 use cis_profile::schema::StandardAttributeString;
 use cis_profile::schema::StandardAttributeValues;
+use serde::Serialize;
+use serde_json::Value;
 
 const ALLOWED_UPDATORS: [PublisherAuthority; 1] = [PublisherAuthority::Mozilliansorg];
 
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Operation {
     Create,
     Update,
     Noop,
 }
 
+/// The outcome of an update for a single attribute path, e.g.
+/// `staff_information.title`. `old` and `new` are null for `Noop`. The display
+/// levels of both values are kept to scrub the audit log.
+#[derive(Clone, Debug, Serialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub operation: Operation,
+    pub publisher: PublisherAuthority,
+    #[serde(skip_serializing_if = "Value::is_null")]
+    pub old: Value,
+    #[serde(skip_serializing_if = "Value::is_null")]
+    pub new: Value,
+    #[serde(skip)]
+    pub old_display: Option<Display>,
+    #[serde(skip)]
+    pub new_display: Option<Display>,
+}
+
+/// The merged profile together with the operation for every field.
+pub struct Updated {
+    pub profile: Profile,
+    pub changes: Vec<FieldChange>,
+}
+
 macro_rules! update {
     ($pf:ident, $uf:ident, $v:ident) => {{
         if $pf.metadata.last_modified > $uf.metadata.last_modified {
@@ -53,7 +83,9 @@ fn update_saac(
 }
 
 macro_rules! update_allowed_any {
-    ($($f:ident).*, $p:ident, $u:ident, $s:ident, $v:ident, $c:ident) => {{
+    ($($f:ident).*, $p:ident, $u:ident, $s:ident, $v:ident, $c:ident, $ch:ident) => {{
+        let field = [$(stringify!($f)),*].join(".");
+        let publisher = $u.$($f).*.signature.publisher.name.clone();
         let op = if $p.$($f).* == $u.$($f).* ||
             !($u.$($f).*.$v.is_some() && ($p.$($f).*.$v != $u.$($f).*.$v ||
                 $p.$($f).*.metadata.display != $u.$($f).*.metadata.display ||
@@ -79,36 +111,62 @@ macro_rules! update_allowed_any {
                 }
             }
         };
-        match op {
-            Ok(Operation::Create) => $c(&mut $p.$($f).*, $u.$($f).*),
-            Ok(Operation::Update) => $c(&mut $p.$($f).*, $u.$($f).*),
-            Ok(Operation::Noop) => Ok(()),
+        let result = match op {
+            Ok(Operation::Noop) => {
+                $ch.push(FieldChange {
+                    field: field.clone(),
+                    operation: Operation::Noop,
+                    publisher: publisher.clone(),
+                    old: Value::Null,
+                    new: Value::Null,
+                    old_display: None,
+                    new_display: None,
+                });
+                Ok(())
+            }
+            Ok(operation) => {
+                let change = FieldChange {
+                    field: field.clone(),
+                    operation,
+                    publisher: publisher.clone(),
+                    old: serde_json::to_value(&$p.$($f).*.$v).unwrap_or_default(),
+                    new: serde_json::to_value(&$u.$($f).*.$v).unwrap_or_default(),
+                    old_display: $p.$($f).*.metadata.display.clone(),
+                    new_display: $u.$($f).*.metadata.display.clone(),
+                };
+                $c(&mut $p.$($f).*, $u.$($f).*).map(|_| $ch.push(change))
+            }
             Err(e) => Err(e),
-        }
+        };
+        result.map_err(|error| UpdateError {
+            error,
+            field,
+            publisher,
+        })
     }};
 }
 
 macro_rules! update_allowed_sas {
-    ($($f:ident).*, $p:ident, $u:ident, $s:ident) => {
-        update_allowed_any!($($f).*, $p, $u, $s, value, update_sas)
+    ($($f:ident).*, $p:ident, $u:ident, $s:ident, $ch:ident) => {
+        update_allowed_any!($($f).*, $p, $u, $s, value, update_sas, $ch)
     };
 }
 
 macro_rules! update_allowed_sav {
-    ($($f:ident).*, $p:ident, $u:ident, $s:ident) => {
-        update_allowed_any!($($f).*, $p, $u, $s, values, update_sav)
+    ($($f:ident).*, $p:ident, $u:ident, $s:ident, $ch:ident) => {
+        update_allowed_any!($($f).*, $p, $u, $s, values, update_sav, $ch)
     };
 }
 
 macro_rules! update_allowed_sab {
-    ($($f:ident).*, $p:ident, $u:ident, $s:ident) => {
-        update_allowed_any!($($f).*, $p, $u, $s, value, update_sab)
+    ($($f:ident).*, $p:ident, $u:ident, $s:ident, $ch:ident) => {
+        update_allowed_any!($($f).*, $p, $u, $s, value, update_sab, $ch)
     };
 }
 
 macro_rules! update_allowed_saac {
-    ($($f:ident).*, $p:ident, $u:ident, $s:ident) => {
-        update_allowed_any!($($f).*, $p, $u, $s, values, update_saac)
+    ($($f:ident).*, $p:ident, $u:ident, $s:ident, $ch:ident) => {
+        update_allowed_any!($($f).*, $p, $u, $s, values, update_saac, $ch)
     };
 }
 
@@ -118,79 +176,96 @@ macro_rules! update_allowed {
     };
 }
 
-pub async fn update(mut p: Profile, u: Profile) -> Result<Profile, ProfileError> {
-    let rules = PUBLISHER_RULES.get().await.unwrap().rules;
-    update_allowed_sas!(uuid, p, u, rules)?;
-    update_allowed_sas!(user_id, p, u, rules)?;
-    update_allowed_sas!(primary_username, p, u, rules)?;
-    update_allowed_sas!(login_method, p, u, rules)?;
-    update_allowed_sab!(active, p, u, rules)?;
-    update_allowed_sas!(last_modified, p, u, rules)?;
-    update_allowed_sas!(created, p, u, rules)?;
-    update_allowed_sav!(usernames, p, u, rules)?;
-    update_allowed_sas!(pronouns, p, u, rules)?;
-    update_allowed_sas!(first_name, p, u, rules)?;
-    update_allowed_sas!(last_name, p, u, rules)?;
-    update_allowed_sas!(alternative_name, p, u, rules)?;
-    update_allowed_sas!(primary_email, p, u, rules)?;
-    update_allowed_sav!(ssh_public_keys, p, u, rules)?;
-    update_allowed_sav!(pgp_public_keys, p, u, rules)?;
-    update_allowed_sas!(fun_title, p, u, rules)?;
-    update_allowed_sas!(description, p, u, rules)?;
-    update_allowed_sas!(location, p, u, rules)?;
-    update_allowed_sas!(timezone, p, u, rules)?;
-    update_allowed_sav!(languages, p, u, rules)?;
-    update_allowed_sav!(tags, p, u, rules)?;
-    update_allowed_sas!(picture, p, u, rules)?;
-    update_allowed_sav!(uris, p, u, rules)?;
-    update_allowed_sav!(phone_numbers, p, u, rules)?;
+pub fn update(mut p: Profile, u: Profile, rules: &PublisherRules) -> Result<Updated, UpdateError> {
+    let mut changes = vec![];
+    update_allowed_sas!(uuid, p, u, rules, changes)?;
+    update_allowed_sas!(user_id, p, u, rules, changes)?;
+    update_allowed_sas!(primary_username, p, u, rules, changes)?;
+    update_allowed_sas!(login_method, p, u, rules, changes)?;
+    update_allowed_sab!(active, p, u, rules, changes)?;
+    update_allowed_sas!(last_modified, p, u, rules, changes)?;
+    update_allowed_sas!(created, p, u, rules, changes)?;
+    update_allowed_sav!(usernames, p, u, rules, changes)?;
+    update_allowed_sas!(pronouns, p, u, rules, changes)?;
+    update_allowed_sas!(first_name, p, u, rules, changes)?;
+    update_allowed_sas!(last_name, p, u, rules, changes)?;
+    update_allowed_sas!(alternative_name, p, u, rules, changes)?;
+    update_allowed_sas!(primary_email, p, u, rules, changes)?;
+    update_allowed_sav!(ssh_public_keys, p, u, rules, changes)?;
+    update_allowed_sav!(pgp_public_keys, p, u, rules, changes)?;
+    update_allowed_sas!(fun_title, p, u, rules, changes)?;
+    update_allowed_sas!(description, p, u, rules, changes)?;
+    update_allowed_sas!(location, p, u, rules, changes)?;
+    update_allowed_sas!(timezone, p, u, rules, changes)?;
+    update_allowed_sav!(languages, p, u, rules, changes)?;
+    update_allowed_sav!(tags, p, u, rules, changes)?;
+    update_allowed_sas!(picture, p, u, rules, changes)?;
+    update_allowed_sav!(uris, p, u, rules, changes)?;
+    update_allowed_sav!(phone_numbers, p, u, rules, changes)?;
 
-    update_allowed_sas!(identities.github_id_v3, p, u, rules)?;
-    update_allowed_sas!(identities.github_id_v4, p, u, rules)?;
-    update_allowed_sas!(identities.github_primary_email, p, u, rules)?;
-    update_allowed_sas!(identities.mozilliansorg_id, p, u, rules)?;
-    update_allowed_sas!(identities.bugzilla_mozilla_org_id, p, u, rules)?;
-    update_allowed_sas!(identities.bugzilla_mozilla_org_primary_email, p, u, rules)?;
-    update_allowed_sas!(identities.mozilla_ldap_id, p, u, rules)?;
-    update_allowed_sas!(identities.mozilla_ldap_primary_email, p, u, rules)?;
-    update_allowed_sas!(identities.mozilla_posix_id, p, u, rules)?;
-    update_allowed_sas!(identities.google_oauth2_id, p, u, rules)?;
-    update_allowed_sas!(identities.google_primary_email, p, u, rules)?;
-    update_allowed_sas!(identities.firefox_accounts_id, p, u, rules)?;
-    update_allowed_sas!(identities.firefox_accounts_primary_email, p, u, rules)?;
-    update_allowed_sas!(identities.custom_1_primary_email, p, u, rules)?;
-    update_allowed_sas!(identities.custom_2_primary_email, p, u, rules)?;
-    update_allowed_sas!(identities.custom_3_primary_email, p, u, rules)?;
+    update_allowed_sas!(identities.github_id_v3, p, u, rules, changes)?;
+    update_allowed_sas!(identities.github_id_v4, p, u, rules, changes)?;
+    update_allowed_sas!(identities.github_primary_email, p, u, rules, changes)?;
+    update_allowed_sas!(identities.mozilliansorg_id, p, u, rules, changes)?;
+    update_allowed_sas!(identities.bugzilla_mozilla_org_id, p, u, rules, changes)?;
+    update_allowed_sas!(
+        identities.bugzilla_mozilla_org_primary_email,
+        p,
+        u,
+        rules,
+        changes
+    )?;
+    update_allowed_sas!(identities.mozilla_ldap_id, p, u, rules, changes)?;
+    update_allowed_sas!(identities.mozilla_ldap_primary_email, p, u, rules, changes)?;
+    update_allowed_sas!(identities.mozilla_posix_id, p, u, rules, changes)?;
+    update_allowed_sas!(identities.google_oauth2_id, p, u, rules, changes)?;
+    update_allowed_sas!(identities.google_primary_email, p, u, rules, changes)?;
+    update_allowed_sas!(identities.firefox_accounts_id, p, u, rules, changes)?;
+    update_allowed_sas!(
+        identities.firefox_accounts_primary_email,
+        p,
+        u,
+        rules,
+        changes
+    )?;
+    update_allowed_sas!(identities.custom_1_primary_email, p, u, rules, changes)?;
+    update_allowed_sas!(identities.custom_2_primary_email, p, u, rules, changes)?;
+    update_allowed_sas!(identities.custom_3_primary_email, p, u, rules, changes)?;
 
-    update_allowed_saac!(access_information.access_provider, p, u, rules)?;
-    update_allowed_saac!(access_information.ldap, p, u, rules)?;
-    update_allowed_saac!(access_information.hris, p, u, rules)?;
-    update_allowed_saac!(access_information.mozilliansorg, p, u, rules)?;
+    update_allowed_saac!(access_information.access_provider, p, u, rules, changes)?;
+    update_allowed_saac!(access_information.ldap, p, u, rules, changes)?;
+    update_allowed_saac!(access_information.hris, p, u, rules, changes)?;
+    update_allowed_saac!(access_information.mozilliansorg, p, u, rules, changes)?;
 
-    update_allowed_sab!(staff_information.manager, p, u, rules)?;
-    update_allowed_sab!(staff_information.director, p, u, rules)?;
-    update_allowed_sab!(staff_information.staff, p, u, rules)?;
-    update_allowed_sas!(staff_information.title, p, u, rules)?;
-    update_allowed_sas!(staff_information.team, p, u, rules)?;
-    update_allowed_sas!(staff_information.cost_center, p, u, rules)?;
-    update_allowed_sas!(staff_information.worker_type, p, u, rules)?;
-    update_allowed_sas!(staff_information.wpr_desk_number, p, u, rules)?;
-    update_allowed_sas!(staff_information.office_location, p, u, rules)?;
-    Ok(p)
+    update_allowed_sab!(staff_information.manager, p, u, rules, changes)?;
+    update_allowed_sab!(staff_information.director, p, u, rules, changes)?;
+    update_allowed_sab!(staff_information.staff, p, u, rules, changes)?;
+    update_allowed_sas!(staff_information.title, p, u, rules, changes)?;
+    update_allowed_sas!(staff_information.team, p, u, rules, changes)?;
+    update_allowed_sas!(staff_information.cost_center, p, u, rules, changes)?;
+    update_allowed_sas!(staff_information.worker_type, p, u, rules, changes)?;
+    update_allowed_sas!(staff_information.wpr_desk_number, p, u, rules, changes)?;
+    update_allowed_sas!(staff_information.office_location, p, u, rules, changes)?;
+    Ok(Updated {
+        profile: p,
+        changes,
+    })
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::profile::publishers::PublisherRules;
     use chrono::Utc;
     use cis_profile::schema::Display;
     use failure::Error;
 
-    #[tokio::test]
-    async fn test_rules() -> Result<(), Error> {
-        let rules: PublisherRules =
-            serde_json::from_str(include_str!("../../tests/data/rules.json"))?;
+    fn rules() -> Result<PublisherRules, Error> {
+        serde_json::from_str(include_str!("../../tests/data/rules.json")).map_err(Into::into)
+    }
+
+    #[test]
+    fn test_rules() -> Result<(), Error> {
+        let rules = rules()?;
         let mut p = Profile::default();
         let mut u = Profile::default();
         u.pronouns.value = Some(String::from("dino"));
@@ -199,32 +274,46 @@ mod test {
         u.user_id.metadata.last_modified = Utc::now();
         u.identities.github_id_v3.value = Some(String::from("dino"));
         u.identities.github_id_v3.metadata.last_modified = Utc::now();
+        let mut changes = vec![];
         assert!(update_allowed!(p.pronouns, rules.update.pronouns));
-        assert!(update_allowed_sas!(identities.github_id_v4, p, u, rules).is_ok());
-        assert!(
-            update_allowed_any!(identities.github_id_v3, p, u, rules, value, update_sas).is_ok()
-        );
-        assert!(update_allowed_sas!(pronouns, p, u, rules).is_ok());
-        assert!(update_allowed_sas!(user_id, p, u, rules).is_err());
+        assert!(update_allowed_sas!(identities.github_id_v4, p, u, rules, changes).is_ok());
+        assert!(update_allowed_any!(
+            identities.github_id_v3,
+            p,
+            u,
+            rules,
+            value,
+            update_sas,
+            changes
+        )
+        .is_ok());
+        assert!(update_allowed_sas!(pronouns, p, u, rules, changes).is_ok());
+        assert!(update_allowed_sas!(user_id, p, u, rules, changes).is_err());
         assert!(!update_allowed!(p.uuid, rules.update.uuid));
+        assert_eq!(changes.len(), 3);
+        assert_eq!(changes[0].field, "identities.github_id_v4");
+        assert_eq!(changes[0].operation, Operation::Noop);
+        assert_eq!(changes[1].field, "identities.github_id_v3");
+        assert_eq!(changes[1].operation, Operation::Create);
+        assert_eq!(changes[2].field, "pronouns");
         Ok(())
     }
 
-    #[tokio::test]
-    async fn test_create() -> Result<(), Error> {
+    #[test]
+    fn test_create() -> Result<(), Error> {
         let p = Profile::default();
         let mut u = Profile::default();
         u.pronouns.value = Some(String::from("dino"));
         u.pronouns.metadata.last_modified = Utc::now();
         u.identities.github_id_v3.value = Some(String::from("dino"));
         u.identities.github_id_v3.metadata.last_modified = Utc::now();
-        let p = update(p, u).await?;
+        let p = update(p, u, &rules()?)?.profile;
         assert_eq!(p.pronouns.value, Some(String::from("dino")));
         Ok(())
     }
 
-    #[tokio::test]
-    async fn test_updators() -> Result<(), Error> {
+    #[test]
+    fn test_updators() -> Result<(), Error> {
         let mut o = Profile::default();
         o.primary_email.value = Some(String::from("dino@dino.dino"));
         o.primary_email.metadata.last_modified = Utc::now();
@@ -232,22 +321,29 @@ mod test {
         o.primary_email.signature.publisher.name = PublisherAuthority::Ldap;
         let p = o.clone();
         let u = o.clone();
-        assert!(update(p, u).await.is_ok());
+        assert!(update(p, u, &rules()?).is_ok());
         let p = o.clone();
         let mut u = o.clone();
         u.primary_email.metadata.display = Some(Display::Public);
         u.primary_email.signature.publisher.name = PublisherAuthority::Ldap;
-        assert!(update(p, u).await.is_err());
+        assert_eq!(
+            update(p, u, &rules()?).err(),
+            Some(UpdateError {
+                error: ProfileError::PublisherNotAllowedToUpdate,
+                field: String::from("primary_email"),
+                publisher: PublisherAuthority::Ldap,
+            })
+        );
         let p = o.clone();
         let mut u = o.clone();
         u.primary_email.metadata.display = Some(Display::Public);
         u.primary_email.signature.publisher.name = PublisherAuthority::Mozilliansorg;
-        update(p, u).await?;
+        update(p, u, &rules()?)?;
         Ok(())
     }
 
-    #[tokio::test]
-    async fn test_update() -> Result<(), Error> {
+    #[test]
+    fn test_update() -> Result<(), Error> {
         let mut o = Profile::default();
         o.primary_email.value = Some(String::from("dino@dino.dino"));
         o.primary_email.metadata.last_modified = Utc::now();
@@ -255,15 +351,31 @@ mod test {
         o.primary_email.signature.publisher.name = PublisherAuthority::Ldap;
         let p = o.clone();
         let u = o.clone();
-        let p = update(p, u.clone()).await?;
-        assert_eq!(p, u);
+        let updated = update(p, u.clone(), &rules()?)?;
+        assert_eq!(updated.profile, u);
+        assert!(updated
+            .changes
+            .iter()
+            .all(|c| c.operation == Operation::Noop));
         let p = o.clone();
         let mut u = o.clone();
         o.primary_email.metadata.last_modified = Utc::now();
         u.primary_email.value = Some(String::from("mc@dino.dino"));
         u.primary_email.signature.publisher.name = PublisherAuthority::AccessProvider;
-        let p = update(p, u).await?;
-        assert_eq!(p.primary_email.value, Some(String::from("mc@dino.dino")));
+        let updated = update(p, u, &rules()?)?;
+        assert_eq!(
+            updated.profile.primary_email.value,
+            Some(String::from("mc@dino.dino"))
+        );
+        let change = updated
+            .changes
+            .iter()
+            .find(|c| c.operation != Operation::Noop)
+            .unwrap();
+        assert_eq!(change.field, "primary_email");
+        assert_eq!(change.operation, Operation::Update);
+        assert_eq!(change.old, "dino@dino.dino");
+        assert_eq!(change.new, "mc@dino.dino");
         Ok(())
     }
 }
@@ -0,0 +1,357 @@
+use crate::db::model::IDENTITIES;
+use crate::error::RulesError;
+use crate::profile::publishers::PublisherRules;
+use failure::Error;
+use log::warn;
+use serde::Serialize;
+use serde_json::Map;
+use serde_json::Value;
+
+const PUBLISHERS: &[&str] = &["ldap", "mozilliansorg", "hris", "cis", "access_provider"];
+
+const FIELDS: &[&str] = &[
+    "uuid",
+    "user_id",
+    "primary_username",
+    "login_method",
+    "active",
+    "last_modified",
+    "created",
+    "usernames",
+    "pronouns",
+    "first_name",
+    "last_name",
+    "alternative_name",
+    "primary_email",
+    "ssh_public_keys",
+    "pgp_public_keys",
+    "fun_title",
+    "description",
+    "location",
+    "timezone",
+    "languages",
+    "tags",
+    "picture",
+    "uris",
+    "phone_numbers",
+];
+
+const ACCESS_INFORMATION: &[&str] = &["access_provider", "ldap", "hris", "mozilliansorg"];
+
+const STAFF_INFORMATION: &[&str] = &[
+    "manager",
+    "director",
+    "staff",
+    "title",
+    "team",
+    "cost_center",
+    "worker_type",
+    "wpr_desk_number",
+    "office_location",
+];
+
+/// Nested rule groups with their fields and whether a single publisher string
+/// may stand in for the whole group.
+const GROUPS: &[(&str, &[&str], bool)] = &[
+    ("identities", IDENTITIES, true),
+    ("access_information", ACCESS_INFORMATION, false),
+    ("staff_information", STAFF_INFORMATION, true),
+];
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueKind {
+    UnknownPublisher,
+    UnknownField,
+    MissingField,
+    InvalidValue,
+    NeverCreatable,
+    UpdateWithoutCreate,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Issue {
+    pub kind: IssueKind,
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+/// Problems found in a publisher rules document. Errors make the document
+/// unusable, warnings point at rules which are valid but likely unintended.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct RulesReport {
+    pub errors: Vec<Issue>,
+    pub warnings: Vec<Issue>,
+}
+
+impl RulesReport {
+    fn error(&mut self, kind: IssueKind, path: String, detail: Option<String>) {
+        self.errors.push(Issue { kind, path, detail });
+    }
+
+    fn warning(&mut self, kind: IssueKind, path: String) {
+        self.warnings.push(Issue {
+            kind,
+            path,
+            detail: None,
+        });
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// Validates a publisher rules document without deserializing it.
+pub fn validate_rules(v: &Value) -> RulesReport {
+    let mut report = RulesReport::default();
+    let root = match v.as_object() {
+        Some(root) => root,
+        None => {
+            report.error(IssueKind::InvalidValue, String::new(), None);
+            return report;
+        }
+    };
+    unknown_keys(&mut report, root, &["create", "update"], "");
+    let create = operation(&mut report, root, "create");
+    let update = operation(&mut report, root, "update");
+    if let (Some(create), Some(update)) = (create, update) {
+        contradictions(&mut report, create, update);
+    }
+    report
+}
+
+/// Validates and deserializes a publisher rules document. Warnings are logged.
+pub fn rules_from_value(v: Value) -> Result<PublisherRules, Error> {
+    let report = validate_rules(&v);
+    if !report.is_valid() {
+        return Err(RulesError::Invalid(report.errors).into());
+    }
+    for issue in &report.warnings {
+        warn!("publisher rules: {:?} at {}", issue.kind, issue.path);
+    }
+    Ok(serde_json::from_value(v)?)
+}
+
+pub fn rules_from_str(s: &str) -> Result<PublisherRules, Error> {
+    rules_from_value(serde_json::from_str(s)?)
+}
+
+fn operation<'a>(
+    report: &mut RulesReport,
+    root: &'a Map<String, Value>,
+    name: &str,
+) -> Option<&'a Map<String, Value>> {
+    let rules = match root.get(name) {
+        Some(Value::Object(rules)) => rules,
+        Some(_) => {
+            report.error(IssueKind::InvalidValue, name.to_owned(), None);
+            return None;
+        }
+        None => {
+            report.error(IssueKind::MissingField, name.to_owned(), None);
+            return None;
+        }
+    };
+    let groups = GROUPS.iter().map(|(group, _, _)| *group);
+    let known: Vec<&str> = FIELDS.iter().copied().chain(groups).collect();
+    unknown_keys(report, rules, &known, name);
+    for field in FIELDS {
+        publishers(report, rules.get(*field), &format!("{}.{}", name, field));
+    }
+    for (group, fields, from_string) in GROUPS {
+        let path = format!("{}.{}", name, group);
+        match rules.get(*group) {
+            Some(Value::Object(group_rules)) => {
+                unknown_keys(report, group_rules, fields, &path);
+                for field in fields.iter() {
+                    publishers(
+                        report,
+                        group_rules.get(*field),
+                        &format!("{}.{}", path, field),
+                    );
+                }
+            }
+            Some(Value::String(_)) if *from_string => publishers(report, rules.get(*group), &path),
+            Some(_) => report.error(IssueKind::InvalidValue, path, None),
+            None => report.error(IssueKind::MissingField, path, None),
+        }
+    }
+    Some(rules)
+}
+
+fn unknown_keys(report: &mut RulesReport, map: &Map<String, Value>, known: &[&str], path: &str) {
+    for key in map.keys().filter(|k| !known.contains(&k.as_str())) {
+        report.error(IssueKind::UnknownField, join(path, key), None);
+    }
+}
+
+fn publishers(report: &mut RulesReport, v: Option<&Value>, path: &str) {
+    match v {
+        // A single empty string means nobody.
+        Some(Value::String(p)) if p.is_empty() || PUBLISHERS.contains(&p.as_str()) => {}
+        Some(Value::String(p)) => report.error(
+            IssueKind::UnknownPublisher,
+            path.to_owned(),
+            Some(p.clone()),
+        ),
+        Some(Value::Array(ps)) => {
+            for p in ps {
+                match p.as_str() {
+                    Some(p) if PUBLISHERS.contains(&p) => {}
+                    Some(p) => report.error(
+                        IssueKind::UnknownPublisher,
+                        path.to_owned(),
+                        Some(p.to_owned()),
+                    ),
+                    None => report.error(IssueKind::InvalidValue, path.to_owned(), None),
+                }
+            }
+        }
+        Some(_) => report.error(IssueKind::InvalidValue, path.to_owned(), None),
+        None => report.error(IssueKind::MissingField, path.to_owned(), None),
+    }
+}
+
+fn nobody(v: Option<&Value>) -> bool {
+    match v {
+        Some(Value::String(p)) => p.is_empty(),
+        Some(Value::Array(ps)) => ps.is_empty(),
+        _ => false,
+    }
+}
+
+fn contradiction(
+    report: &mut RulesReport,
+    create: Option<&Value>,
+    update: Option<&Value>,
+    path: String,
+) {
+    if nobody(create) {
+        if nobody(update) {
+            report.warning(IssueKind::NeverCreatable, path);
+        } else {
+            report.warning(IssueKind::UpdateWithoutCreate, path);
+        }
+    }
+}
+
+fn contradictions(
+    report: &mut RulesReport,
+    create: &Map<String, Value>,
+    update: &Map<String, Value>,
+) {
+    for field in FIELDS {
+        contradiction(
+            report,
+            create.get(*field),
+            update.get(*field),
+            field.to_string(),
+        );
+    }
+    for (group, fields, _) in GROUPS {
+        for field in fields.iter() {
+            let lookup = |rules: &'_ Map<String, Value>| match rules.get(*group) {
+                Some(Value::Object(group_rules)) => group_rules.get(*field).cloned(),
+                other => other.cloned(),
+            };
+            contradiction(
+                report,
+                lookup(create).as_ref(),
+                lookup(update).as_ref(),
+                format!("{}.{}", group, field),
+            );
+        }
+    }
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_owned()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn rules() -> Value {
+        serde_json::from_str(include_str!("../../tests/data/rules.json")).unwrap()
+    }
+
+    #[test]
+    fn test_valid_rules() {
+        let report = validate_rules(&rules());
+        assert!(report.is_valid());
+        assert!(rules_from_value(rules()).is_ok());
+    }
+
+    #[test]
+    fn test_unknown_publisher_and_field() {
+        let mut v = rules();
+        v["create"]["first_name"] = json!(["ldap", "nobody"]);
+        v["update"]["staff_information"] = json!("somebody");
+        v["update"]["favourite_color"] = json!("ldap");
+        let report = validate_rules(&v);
+        assert_eq!(
+            report.errors,
+            vec![
+                Issue {
+                    kind: IssueKind::UnknownPublisher,
+                    path: String::from("create.first_name"),
+                    detail: Some(String::from("nobody")),
+                },
+                Issue {
+                    kind: IssueKind::UnknownField,
+                    path: String::from("update.favourite_color"),
+                    detail: None,
+                },
+                Issue {
+                    kind: IssueKind::UnknownPublisher,
+                    path: String::from("update.staff_information"),
+                    detail: Some(String::from("somebody")),
+                },
+            ]
+        );
+        assert!(rules_from_value(v).is_err());
+    }
+
+    #[test]
+    fn test_missing_fields() {
+        let mut v = rules();
+        v["create"].as_object_mut().unwrap().remove("pronouns");
+        v.as_object_mut().unwrap().remove("update");
+        let report = validate_rules(&v);
+        let paths: Vec<&str> = report.errors.iter().map(|i| i.path.as_str()).collect();
+        assert_eq!(paths, vec!["create.pronouns", "update"]);
+    }
+
+    #[test]
+    fn test_contradictions() {
+        let mut v = rules();
+        v["create"]["pronouns"] = json!([]);
+        v["create"]["tags"] = json!([]);
+        v["update"]["tags"] = json!("");
+        let report = validate_rules(&v);
+        assert!(report.is_valid());
+        assert_eq!(
+            report.warnings,
+            vec![
+                Issue {
+                    kind: IssueKind::UpdateWithoutCreate,
+                    path: String::from("pronouns"),
+                    detail: None,
+                },
+                Issue {
+                    kind: IssueKind::NeverCreatable,
+                    path: String::from("tags"),
+                    detail: None,
+                },
+            ]
+        );
+    }
+}
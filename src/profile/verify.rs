@@ -0,0 +1,82 @@
+use crate::error::ProfileError;
+use cis_profile::crypto::SecretStore;
+use cis_profile::crypto::Verifier;
+use cis_profile::schema::Profile;
+use log::warn;
+
+macro_rules! verify {
+    ($($f:ident).*, $p:ident, $s:ident, $v:ident) => {
+        if $p.$($f).*.$v.is_some() {
+            $s.verify_attribute(&$p.$($f).*).map_err(|e| {
+                warn!("invalid signature for {}: {}", stringify!($($f).*), e);
+                ProfileError::InvalidSignature
+            })?;
+        }
+    };
+}
+
+/// Verifies the signature of every attribute in `p` which carries a value.
+pub fn verify_full_profile(store: &SecretStore, p: &Profile) -> Result<(), ProfileError> {
+    verify!(uuid, p, store, value);
+    verify!(user_id, p, store, value);
+    verify!(primary_username, p, store, value);
+    verify!(login_method, p, store, value);
+    verify!(active, p, store, value);
+    verify!(last_modified, p, store, value);
+    verify!(created, p, store, value);
+    verify!(usernames, p, store, values);
+    verify!(pronouns, p, store, value);
+    verify!(first_name, p, store, value);
+    verify!(last_name, p, store, value);
+    verify!(alternative_name, p, store, value);
+    verify!(primary_email, p, store, value);
+    verify!(ssh_public_keys, p, store, values);
+    verify!(pgp_public_keys, p, store, values);
+    verify!(fun_title, p, store, value);
+    verify!(description, p, store, value);
+    verify!(location, p, store, value);
+    verify!(timezone, p, store, value);
+    verify!(languages, p, store, values);
+    verify!(tags, p, store, values);
+    verify!(picture, p, store, value);
+    verify!(uris, p, store, values);
+    verify!(phone_numbers, p, store, values);
+
+    verify!(identities.github_id_v3, p, store, value);
+    verify!(identities.github_id_v4, p, store, value);
+    verify!(identities.github_primary_email, p, store, value);
+    verify!(identities.mozilliansorg_id, p, store, value);
+    verify!(identities.bugzilla_mozilla_org_id, p, store, value);
+    verify!(
+        identities.bugzilla_mozilla_org_primary_email,
+        p,
+        store,
+        value
+    );
+    verify!(identities.mozilla_ldap_id, p, store, value);
+    verify!(identities.mozilla_ldap_primary_email, p, store, value);
+    verify!(identities.mozilla_posix_id, p, store, value);
+    verify!(identities.google_oauth2_id, p, store, value);
+    verify!(identities.google_primary_email, p, store, value);
+    verify!(identities.firefox_accounts_id, p, store, value);
+    verify!(identities.firefox_accounts_primary_email, p, store, value);
+    verify!(identities.custom_1_primary_email, p, store, value);
+    verify!(identities.custom_2_primary_email, p, store, value);
+    verify!(identities.custom_3_primary_email, p, store, value);
+
+    verify!(access_information.access_provider, p, store, values);
+    verify!(access_information.ldap, p, store, values);
+    verify!(access_information.hris, p, store, values);
+    verify!(access_information.mozilliansorg, p, store, values);
+
+    verify!(staff_information.manager, p, store, value);
+    verify!(staff_information.director, p, store, value);
+    verify!(staff_information.staff, p, store, value);
+    verify!(staff_information.title, p, store, value);
+    verify!(staff_information.team, p, store, value);
+    verify!(staff_information.cost_center, p, store, value);
+    verify!(staff_information.worker_type, p, store, value);
+    verify!(staff_information.wpr_desk_number, p, store, value);
+    verify!(staff_information.office_location, p, store, value);
+    Ok(())
+}
@@ -1,4 +1,9 @@
+use config::Config;
+use config::ConfigError;
+use config::Environment;
+use config::File;
 use serde::Deserialize;
+use std::env;
 
 #[derive(Clone, Debug, Deserialize, Default)]
 pub struct Keys {
@@ -11,8 +16,57 @@ pub struct Keys {
     pub access_provider_key: Option<String>,
 }
 
+#[derive(Clone, Debug, Deserialize, Default)]
+pub struct PublisherRulesSettings {
+    pub source: String,
+    pub url: Option<String>,
+    pub file: Option<String>,
+    pub inline: Option<String>,
+    pub fallback_file: Option<String>,
+}
+
 #[derive(Clone, Debug, Deserialize, Default)]
 pub struct CisSettings {
     pub sign_keys: Keys,
     pub verify_keys: Keys,
+    pub publisher_rules: PublisherRulesSettings,
+    pub uuid_salt: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Default)]
+pub struct WebhookSettings {
+    pub max_attempts: i32,
+    pub backoff_seconds: i64,
+    pub poll_interval_ms: u64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Settings {
+    pub cis: CisSettings,
+    pub auth: String,
+    pub postgres_url: String,
+    pub bind: String,
+    pub shutdown_timeout: u64,
+    pub webhooks: WebhookSettings,
+}
+
+impl Settings {
+    pub fn new() -> Result<Self, ConfigError> {
+        let file = env::var("DPC_SETTINGS").unwrap_or_else(|_| String::from(".settings"));
+        let mut s = Config::new();
+        s.set_default("auth", "https://auth.mozilla.auth0.com/")?;
+        s.set_default("cis.publisher_rules.source", "remote")?;
+        s.set_default(
+            "cis.publisher_rules.url",
+            "https://auth.mozilla.com/.well-known/mozilla-iam-publisher-rules",
+        )?;
+        s.set_default("bind", "0.0.0.0:8085")?;
+        s.set_default("shutdown_timeout", 30)?;
+        s.set_default("webhooks.max_attempts", 8)?;
+        s.set_default("webhooks.backoff_seconds", 10)?;
+        s.set_default("webhooks.poll_interval_ms", 1000)?;
+        s.merge(File::with_name(&file).required(false))?;
+        s.merge(Environment::new().separator("__"))?;
+        s.try_into()
+    }
 }
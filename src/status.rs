@@ -0,0 +1,21 @@
+use crate::notify::Broadcaster;
+use crate::profile::publishers::PublicRulesStatus;
+use crate::profile::publishers::PublisherRulesStore;
+use actix_web::dev::HttpServiceFactory;
+use actix_web::web;
+use actix_web::HttpResponse;
+use serde_json::json;
+
+async fn publisher_rules(rules: web::Data<PublisherRulesStore>) -> HttpResponse {
+    HttpResponse::Ok().json(PublicRulesStatus::from(rules.status()))
+}
+
+async fn listener(broadcaster: web::Data<Broadcaster>) -> HttpResponse {
+    HttpResponse::Ok().json(json!({ "listening": broadcaster.is_listening() }))
+}
+
+pub fn status_app() -> impl HttpServiceFactory {
+    web::scope("/status")
+        .service(web::resource("/publisher_rules").route(web::get().to(publisher_rules)))
+        .service(web::resource("/listener").route(web::get().to(listener)))
+}
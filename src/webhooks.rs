@@ -0,0 +1,122 @@
+use crate::db::model::ProfileEvent;
+use crate::db::model::Webhook;
+use crate::db::model::WebhookDelivery;
+use crate::db::webhooks::claim_due;
+use crate::db::webhooks::fan_out;
+use crate::db::webhooks::record_attempt;
+use crate::db::Pool;
+use crate::settings::WebhookSettings;
+use failure::format_err;
+use failure::Error;
+use futures::future;
+use hmac::Hmac;
+use hmac::Mac;
+use log::warn;
+use reqwest::header::CONTENT_TYPE;
+use sha2::Sha256;
+use std::time::Duration;
+
+pub const SIGNATURE_HEADER: &str = "X-CIS-Signature";
+pub const EVENT_ID_HEADER: &str = "X-CIS-Event-Id";
+pub const DELIVERY_ID_HEADER: &str = "X-CIS-Delivery-Id";
+
+const DELIVERY_BATCH: i64 = 50;
+const DELIVERY_TIMEOUT_SECONDS: u64 = 10;
+
+/// Hex encoded HMAC-SHA256 of `body` keyed with the webhook's secret.
+pub fn signature(secret: &str, body: &[u8]) -> Result<String, Error> {
+    let mut mac = Hmac::<Sha256>::new_varkey(secret.as_bytes())
+        .map_err(|_| format_err!("invalid webhook secret"))?;
+    mac.update(body);
+    Ok(hex::encode(mac.finalize().into_bytes()))
+}
+
+async fn deliver(
+    client: &reqwest::Client,
+    webhook: &Webhook,
+    delivery: &WebhookDelivery,
+    event: &ProfileEvent,
+) -> Result<(), Error> {
+    let body = serde_json::to_vec(event)?;
+    let res = client
+        .post(&webhook.url)
+        .header(CONTENT_TYPE, "application/json")
+        .header(
+            SIGNATURE_HEADER,
+            format!("sha256={}", signature(&webhook.secret, &body)?),
+        )
+        .header(EVENT_ID_HEADER, event.id.to_string())
+        .header(DELIVERY_ID_HEADER, delivery.id.to_string())
+        .body(body)
+        .send()
+        .await?;
+    if !res.status().is_success() {
+        return Err(format_err!("unexpected status {}", res.status()));
+    }
+    Ok(())
+}
+
+pub fn client() -> Result<reqwest::Client, Error> {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(DELIVERY_TIMEOUT_SECONDS))
+        .build()
+        .map_err(Into::into)
+}
+
+/// Fans out new events and attempts all due deliveries once. Returns the
+/// number of attempted deliveries. The claimed batch is delivered
+/// concurrently so it completes within one `DELIVERY_TIMEOUT_SECONDS`, well
+/// inside the lease. No pooled connection is held while delivering.
+pub async fn run_once(
+    pool: &Pool,
+    client: &reqwest::Client,
+    settings: &WebhookSettings,
+) -> Result<usize, Error> {
+    let due = {
+        let connection = pool.get()?;
+        fan_out(&connection)?;
+        claim_due(&connection, DELIVERY_BATCH)?
+    };
+    let attempted = due.len();
+    let outcomes = future::join_all(
+        due.iter()
+            .map(|(delivery, webhook, event)| deliver(client, webhook, delivery, event)),
+    )
+    .await;
+    let connection = pool.get()?;
+    for ((delivery, webhook, _), outcome) in due.iter().zip(outcomes) {
+        let error = outcome.err().map(|e| e.to_string());
+        if let Some(ref e) = error {
+            warn!("delivery {} to {} failed: {}", delivery.id, webhook.url, e);
+        }
+        if !record_attempt(&connection, delivery, error, settings)? {
+            warn!("lease on delivery {} expired before recording", delivery.id);
+        }
+    }
+    Ok(attempted)
+}
+
+/// Background worker delivering events to all registered webhooks.
+pub async fn deliver_webhooks(pool: Pool, client: reqwest::Client, settings: WebhookSettings) {
+    loop {
+        if let Err(e) = run_once(&pool, &client, &settings).await {
+            warn!("webhook delivery failed: {}", e);
+        }
+        actix_rt::time::delay_for(Duration::from_millis(settings.poll_interval_ms)).await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_signature() -> Result<(), Error> {
+        // RFC 4231 test case 2
+        assert_eq!(
+            signature("Jefe", b"what do ya want for nothing?")?,
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,32 @@
+use crate::error::ApiError;
+use crate::keys::JwkSet;
+use crate::profile::publishers::PublisherRulesStore;
+use actix_web::dev::HttpServiceFactory;
+use actix_web::http::header::CacheControl;
+use actix_web::http::header::CacheDirective;
+use actix_web::web;
+use actix_web::HttpResponse;
+use chrono::Utc;
+
+async fn publisher_rules(rules: web::Data<PublisherRulesStore>) -> Result<HttpResponse, ApiError> {
+    let current = rules.current().await?;
+    let max_age = (current.valid_till - Utc::now()).num_seconds().max(0) as u32;
+    Ok(HttpResponse::Ok()
+        .set(CacheControl(vec![CacheDirective::MaxAge(max_age)]))
+        .json(current.rules))
+}
+
+async fn publisher_jwks(jwks: web::Data<JwkSet>) -> HttpResponse {
+    if jwks.keys.is_empty() {
+        return HttpResponse::NotFound().finish();
+    }
+    HttpResponse::Ok().json(jwks.get_ref())
+}
+
+pub fn well_known_app() -> impl HttpServiceFactory {
+    web::scope("/.well-known")
+        .service(
+            web::resource("/mozilla-iam-publisher-rules").route(web::get().to(publisher_rules)),
+        )
+        .service(web::resource("/mozilla-iam-publisher-jwks").route(web::get().to(publisher_jwks)))
+}
@@ -1,4 +1,5 @@
 #[macro_use]
 extern crate diesel_migrations;
 mod api;
+mod db;
 mod helpers;
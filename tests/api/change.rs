@@ -0,0 +1,226 @@
+use crate::helpers::api::*;
+use crate::helpers::db::reset;
+use crate::helpers::misc::nobody_soa;
+use crate::helpers::misc::read_json;
+use crate::helpers::misc::staff_soa;
+use crate::helpers::misc::test_app;
+use crate::helpers::misc::test_store;
+use crate::helpers::users::basic_user;
+use crate::helpers::users::sign;
+use crate::helpers::users::signed_user;
+use crate::helpers::users::user_uuid;
+use actix_web::test;
+use actix_web::App;
+use cis_profile::schema::Profile;
+use cis_profile::schema::PublisherAuthority;
+use dino_park_cis::profile::verify::verify_full_profile;
+use failure::Error;
+
+#[actix_rt::test]
+async fn create_user() -> Result<(), Error> {
+    reset()?;
+    let app = App::new().service(test_app().await);
+    let mut app = test::init_service(app).await;
+
+    let user = signed_user(1, true);
+    let res = post(&mut app, "/cis/api/change/v2/user", &user, &nobody_soa()).await;
+    assert!(res.status().is_success());
+    let status = read_json(res).await;
+    assert_eq!(status["uuid"], user_uuid(&user));
+    assert_eq!(status["version"], 1);
+    assert!(status["change_id"].is_string());
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn create_user_unsigned() -> Result<(), Error> {
+    reset()?;
+    let app = App::new().service(test_app().await);
+    let mut app = test::init_service(app).await;
+
+    let user = basic_user(1, true);
+    let res = post(&mut app, "/cis/api/change/v2/user", &user, &nobody_soa()).await;
+    assert!(res.status().is_client_error());
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn create_users_batch() -> Result<(), Error> {
+    reset()?;
+    let app = App::new().service(test_app().await);
+    let mut app = test::init_service(app).await;
+
+    let users = vec![
+        signed_user(1, true),
+        basic_user(2, false),
+        signed_user(3, false),
+    ];
+    let res = post(&mut app, "/cis/api/change/v2/users", &users, &nobody_soa()).await;
+    assert!(res.status().is_success());
+    let results = read_json(res).await;
+    assert_eq!(results[0]["uuid"], user_uuid(&users[0]));
+    assert_eq!(results[0]["version"], 1);
+    assert_eq!(results[1]["user_id"], "fire2");
+    assert_eq!(results[1]["error"], "invalid_signature");
+    assert_eq!(results[2]["uuid"], user_uuid(&users[2]));
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn update_user_diff() -> Result<(), Error> {
+    reset()?;
+    let app = App::new().service(test_app().await);
+    let mut app = test::init_service(app).await;
+
+    let user = signed_user(1, true);
+    let res = post(&mut app, "/cis/api/change/v2/user", &user, &nobody_soa()).await;
+    assert!(res.status().is_success());
+
+    let mut update = user.clone();
+    update.last_name.value = Some(String::from("Knall"));
+    update.last_name.signature.publisher.name = PublisherAuthority::Mozilliansorg;
+    let res = post(
+        &mut app,
+        "/cis/api/change/v2/user",
+        &sign(update),
+        &nobody_soa(),
+    )
+    .await;
+    assert!(res.status().is_success());
+    let status = read_json(res).await;
+    let changes = status["changes"].as_array().unwrap();
+    let last_name = changes.iter().find(|c| c["field"] == "last_name").unwrap();
+    assert_eq!(last_name["operation"], "update");
+    assert_eq!(last_name["old"], "Knall1");
+    assert_eq!(last_name["new"], "Knall");
+    let first_name = changes.iter().find(|c| c["field"] == "first_name").unwrap();
+    assert_eq!(first_name["operation"], "noop");
+
+    let mut update = user.clone();
+    update.first_name.value = Some(String::from("Hans"));
+    let res = post(
+        &mut app,
+        "/cis/api/change/v2/user",
+        &sign(update),
+        &nobody_soa(),
+    )
+    .await;
+    assert!(res.status().is_client_error());
+    let rejection = read_json(res).await;
+    assert_eq!(rejection["error"], "publisher_not_allowed_to_update");
+    assert_eq!(rejection["field"], "first_name");
+    assert_eq!(rejection["publisher"], "ldap");
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn resubmit_unchanged_user() -> Result<(), Error> {
+    reset()?;
+    let app = App::new().service(test_app().await);
+    let mut app = test::init_service(app).await;
+
+    let user = signed_user(1, true);
+    let res = post(&mut app, "/cis/api/change/v2/user", &user, &nobody_soa()).await;
+    assert!(res.status().is_success());
+
+    let res = post(&mut app, "/cis/api/change/v2/user", &user, &nobody_soa()).await;
+    assert!(res.status().is_success());
+    let status = read_json(res).await;
+    assert_eq!(status["version"], 1);
+    assert!(status["change_id"].is_null());
+
+    let history = format!("/cis/api/person/v2/user/uuid/{}/history", user_uuid(&user));
+    let res = get(&mut app, &history, &nobody_soa()).await;
+    assert_eq!(read_json(res).await.as_array().map(Vec::len), Some(0));
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn create_user_dry_run() -> Result<(), Error> {
+    reset()?;
+    let app = App::new().service(test_app().await);
+    let mut app = test::init_service(app).await;
+
+    let user = signed_user(1, true);
+    let res = post(
+        &mut app,
+        "/cis/api/change/v2/user?dry_run=true",
+        &user,
+        &nobody_soa(),
+    )
+    .await;
+    assert!(res.status().is_success());
+    let status = read_json(res).await;
+    assert_eq!(status["uuid"], user_uuid(&user));
+    assert_eq!(status["version"], 1);
+    assert_eq!(status["dry_run"], true);
+    assert!(status["change_id"].is_null());
+
+    let uuid = format!("/cis/api/person/v2/user/uuid/{}", user_uuid(&user));
+    let res = get(&mut app, &uuid, &nobody_soa()).await;
+    assert!(!res.status().is_success());
+
+    let res = post(&mut app, "/cis/api/change/v2/user", &user, &nobody_soa()).await;
+    assert_eq!(read_json(res).await["version"], 1);
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn create_user_generates_cis_attributes() -> Result<(), Error> {
+    reset()?;
+    let app = App::new().service(test_app().await);
+    let mut app = test::init_service(app).await;
+
+    let mut user = signed_user(1, true);
+    user.uuid = Default::default();
+    user.primary_username = Default::default();
+    let res = post(&mut app, "/cis/api/change/v2/user", &user, &nobody_soa()).await;
+    assert!(res.status().is_success());
+    let status = read_json(res).await;
+    let uuid = status["uuid"].as_str().unwrap().to_owned();
+
+    let staff = staff_soa();
+    let res = get(&mut app, "/cis/api/person/v2/user/user_id/fire1", &staff).await;
+    assert!(res.status().is_success());
+    let profile: Profile = serde_json::from_value(read_json(res).await)?;
+    assert_eq!(profile.uuid.value, Some(uuid.clone()));
+    assert!(profile
+        .primary_username
+        .value
+        .as_deref()
+        .unwrap()
+        .starts_with("r--"));
+    assert!(profile.created.value.is_some());
+    assert!(profile.last_modified.value.is_some());
+    assert_eq!(
+        profile.uuid.signature.publisher.name,
+        PublisherAuthority::Cis
+    );
+    assert!(verify_full_profile(&test_store(), &profile).is_ok());
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn generated_uuid_is_stable() -> Result<(), Error> {
+    let mut user = signed_user(1, true);
+    user.uuid = Default::default();
+    user.primary_username = Default::default();
+
+    let mut uuids = vec![];
+    for _ in 0..2 {
+        reset()?;
+        let app = App::new().service(test_app().await);
+        let mut app = test::init_service(app).await;
+        let res = post(
+            &mut app,
+            "/cis/api/change/v2/user?dry_run=true",
+            &user,
+            &nobody_soa(),
+        )
+        .await;
+        assert!(res.status().is_success());
+        uuids.push(read_json(res).await["uuid"].clone());
+    }
+    assert_eq!(uuids[0], uuids[1]);
+    Ok(())
+}
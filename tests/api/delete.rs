@@ -0,0 +1,158 @@
+use crate::helpers::api::*;
+use crate::helpers::db::reset;
+use crate::helpers::misc::nobody_soa;
+use crate::helpers::misc::read_json;
+use crate::helpers::misc::staff_soa;
+use crate::helpers::misc::test_app;
+use crate::helpers::users::deletion_request;
+use crate::helpers::users::signed_user;
+use crate::helpers::users::user_uuid;
+use actix_web::http::StatusCode;
+use actix_web::test;
+use actix_web::App;
+use cis_profile::schema::PublisherAuthority;
+use failure::Error;
+
+#[actix_rt::test]
+async fn soft_delete_user() -> Result<(), Error> {
+    reset()?;
+    let app = App::new().service(test_app().await);
+    let mut app = test::init_service(app).await;
+
+    let user = signed_user(1, true);
+    let res = post(&mut app, "/cis/api/change/v2/user", &user, &nobody_soa()).await;
+    assert!(res.status().is_success());
+
+    let endpoint = format!("/cis/api/change/v2/user/{}", user_uuid(&user));
+    let request = deletion_request(1, PublisherAuthority::Hris);
+    let res = delete_json(&mut app, &endpoint, &request, &nobody_soa()).await;
+    assert!(res.status().is_success());
+    let tombstone = read_json(res).await;
+    assert_eq!(tombstone["deletion"], "soft");
+    assert_eq!(tombstone["version"], 2);
+    assert_eq!(tombstone["publisher"], "hris");
+
+    let staff = staff_soa();
+    let res = get(&mut app, "/cis/api/person/v2/user/user_id/fire1", &staff).await;
+    let profile = read_json(res).await;
+    assert_eq!(profile["active"]["value"], false);
+    assert_eq!(profile["primary_email"]["value"], "hans1@knall.org");
+    assert!(profile["first_name"]["value"].is_null());
+
+    let res = get(
+        &mut app,
+        "/cis/api/person/v2/user/user_id/fire1?active=true",
+        &staff,
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    let history = format!("/cis/api/person/v2/user/uuid/{}/history", user_uuid(&user));
+    let res = get(&mut app, &history, &staff).await;
+    assert_eq!(read_json(res).await.as_array().map(Vec::len), Some(0));
+    let res = get(&mut app, &format!("{}/1", history), &staff).await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn hard_delete_user() -> Result<(), Error> {
+    reset()?;
+    let app = App::new().service(test_app().await);
+    let mut app = test::init_service(app).await;
+
+    let user = signed_user(1, true);
+    let res = post(&mut app, "/cis/api/change/v2/user", &user, &nobody_soa()).await;
+    assert!(res.status().is_success());
+
+    let endpoint = format!("/cis/api/change/v2/user/{}?mode=hard", user_uuid(&user));
+    let request = deletion_request(1, PublisherAuthority::Ldap);
+    let res = delete_json(&mut app, &endpoint, &request, &nobody_soa()).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let request = deletion_request(1, PublisherAuthority::Cis);
+    let res = delete_json(&mut app, &endpoint, &request, &nobody_soa()).await;
+    assert!(res.status().is_success());
+    let tombstone = read_json(res).await;
+    assert_eq!(tombstone["deletion"], "hard");
+    assert_eq!(tombstone["version"], 1);
+
+    let res = get(
+        &mut app,
+        &format!("/cis/api/person/v2/user/uuid/{}", user_uuid(&user)),
+        &nobody_soa(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::GONE);
+    let res = get(
+        &mut app,
+        &format!("/cis/api/person/v2/user/uuid/{}/history", user_uuid(&user)),
+        &nobody_soa(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::GONE);
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn hard_delete_soft_deleted_user() -> Result<(), Error> {
+    reset()?;
+    let app = App::new().service(test_app().await);
+    let mut app = test::init_service(app).await;
+
+    let user = signed_user(1, true);
+    let res = post(&mut app, "/cis/api/change/v2/user", &user, &nobody_soa()).await;
+    assert!(res.status().is_success());
+
+    let endpoint = format!("/cis/api/change/v2/user/{}", user_uuid(&user));
+    let request = deletion_request(1, PublisherAuthority::Ldap);
+    let res = delete_json(&mut app, &endpoint, &request, &nobody_soa()).await;
+    assert!(res.status().is_success());
+
+    let endpoint = format!("/cis/api/change/v2/user/{}?mode=hard", user_uuid(&user));
+    let request = deletion_request(1, PublisherAuthority::Cis);
+    let res = delete_json(&mut app, &endpoint, &request, &nobody_soa()).await;
+    assert!(res.status().is_success());
+    let tombstone = read_json(res).await;
+    assert_eq!(tombstone["deletion"], "hard");
+    assert_eq!(tombstone["version"], 2);
+
+    let res = get(
+        &mut app,
+        &format!("/cis/api/person/v2/user/uuid/{}", user_uuid(&user)),
+        &nobody_soa(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::GONE);
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn delete_user_rejected() -> Result<(), Error> {
+    reset()?;
+    let app = App::new().service(test_app().await);
+    let mut app = test::init_service(app).await;
+
+    let user = signed_user(1, true);
+    let res = post(&mut app, "/cis/api/change/v2/user", &user, &nobody_soa()).await;
+    assert!(res.status().is_success());
+
+    let endpoint = format!("/cis/api/change/v2/user/{}", user_uuid(&user));
+    let request = deletion_request(1, PublisherAuthority::Mozilliansorg);
+    let res = delete_json(&mut app, &endpoint, &request, &nobody_soa()).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    assert_eq!(
+        read_json(res).await["error"],
+        "publisher_not_allowed_to_delete"
+    );
+
+    let request = deletion_request(2, PublisherAuthority::Hris);
+    let res = delete_json(&mut app, &endpoint, &request, &nobody_soa()).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let endpoint = format!("/cis/api/change/v2/user/{}?version=5", user_uuid(&user));
+    let request = deletion_request(1, PublisherAuthority::Hris);
+    let res = delete_json(&mut app, &endpoint, &request, &nobody_soa()).await;
+    assert_eq!(res.status(), StatusCode::CONFLICT);
+    Ok(())
+}
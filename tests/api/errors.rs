@@ -0,0 +1,280 @@
+use crate::helpers::api::*;
+use crate::helpers::db::reset;
+use crate::helpers::misc::nobody_soa;
+use crate::helpers::misc::read_json;
+use crate::helpers::misc::test_app;
+use crate::helpers::users::basic_user;
+use crate::helpers::users::sign;
+use crate::helpers::users::signed_user;
+use actix_web::test;
+use actix_web::App;
+use chrono::Duration;
+use cis_profile::schema::Profile;
+use cis_profile::schema::PublisherAuthority;
+use failure::Error;
+use serde_json::json;
+
+#[actix_rt::test]
+async fn not_found() -> Result<(), Error> {
+    reset()?;
+    let app = App::new().service(test_app().await);
+    let mut app = test::init_service(app).await;
+
+    let res = get(
+        &mut app,
+        "/cis/api/person/v2/user/user_id/nobody",
+        &nobody_soa(),
+    )
+    .await;
+    assert_eq!(res.status().as_u16(), 404);
+    assert_eq!(
+        read_json(res).await,
+        json!({ "error": "profile_not_found" })
+    );
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn invalid_profile() -> Result<(), Error> {
+    reset()?;
+    let app = App::new().service(test_app().await);
+    let mut app = test::init_service(app).await;
+
+    let res = post(
+        &mut app,
+        "/cis/api/change/v2/user",
+        &Profile::default(),
+        &nobody_soa(),
+    )
+    .await;
+    assert_eq!(res.status().as_u16(), 400);
+    assert_eq!(
+        read_json(res).await,
+        json!({ "error": "db_invalid_profile_v2" })
+    );
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn invalid_signature() -> Result<(), Error> {
+    reset()?;
+    let app = App::new().service(test_app().await);
+    let mut app = test::init_service(app).await;
+
+    let res = post(
+        &mut app,
+        "/cis/api/change/v2/user",
+        &basic_user(1, false),
+        &nobody_soa(),
+    )
+    .await;
+    assert_eq!(res.status().as_u16(), 403);
+    assert_eq!(
+        read_json(res).await,
+        json!({ "error": "invalid_signature" })
+    );
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn publisher_not_allowed() -> Result<(), Error> {
+    reset()?;
+    let app = App::new().service(test_app().await);
+    let mut app = test::init_service(app).await;
+
+    let mut user = signed_user(1, false);
+    user.uuid.signature.publisher.name = PublisherAuthority::Ldap;
+    let res = post(
+        &mut app,
+        "/cis/api/change/v2/user",
+        &sign(user),
+        &nobody_soa(),
+    )
+    .await;
+    assert_eq!(res.status().as_u16(), 403);
+    assert_eq!(
+        read_json(res).await,
+        json!({
+            "error": "publisher_not_allowed_to_create",
+            "field": "uuid",
+            "publisher": "ldap"
+        })
+    );
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn outdated_update() -> Result<(), Error> {
+    reset()?;
+    let app = App::new().service(test_app().await);
+    let mut app = test::init_service(app).await;
+
+    let user = signed_user(1, false);
+    let res = post(&mut app, "/cis/api/change/v2/user", &user, &nobody_soa()).await;
+    assert!(res.status().is_success());
+
+    let mut update = user.clone();
+    update.last_name.value = Some(String::from("Knall"));
+    update.last_name.signature.publisher.name = PublisherAuthority::Mozilliansorg;
+    update.last_name.metadata.last_modified =
+        user.last_name.metadata.last_modified - Duration::days(1);
+    let res = post(
+        &mut app,
+        "/cis/api/change/v2/user",
+        &sign(update),
+        &nobody_soa(),
+    )
+    .await;
+    assert_eq!(res.status().as_u16(), 409);
+    assert_eq!(
+        read_json(res).await,
+        json!({
+            "error": "outdated_update",
+            "field": "last_name",
+            "publisher": "mozilliansorg"
+        })
+    );
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn identity_conflict() -> Result<(), Error> {
+    reset()?;
+    let app = App::new().service(test_app().await);
+    let mut app = test::init_service(app).await;
+
+    let with_github = |n| {
+        let mut user = signed_user(n, false);
+        user.identities.github_id_v4.value = Some(String::from("MDQ6VXNlcjE="));
+        user.identities.github_id_v4.signature.publisher.name = PublisherAuthority::Ldap;
+        sign(user)
+    };
+    let user = with_github(1);
+    let res = post(&mut app, "/cis/api/change/v2/user", &user, &nobody_soa()).await;
+    assert!(res.status().is_success());
+    let res = post(&mut app, "/cis/api/change/v2/user", &user, &nobody_soa()).await;
+    assert!(res.status().is_success());
+
+    let res = post(
+        &mut app,
+        "/cis/api/change/v2/user",
+        &with_github(2),
+        &nobody_soa(),
+    )
+    .await;
+    assert_eq!(res.status().as_u16(), 409);
+    assert_eq!(
+        read_json(res).await,
+        json!({
+            "error": "identity_conflict",
+            "identity": "github_id_v4"
+        })
+    );
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn empty_identities_do_not_conflict() -> Result<(), Error> {
+    reset()?;
+    let app = App::new().service(test_app().await);
+    let mut app = test::init_service(app).await;
+
+    for n in 1..=2 {
+        let mut user = signed_user(n, false);
+        user.identities.github_id_v4.value = Some(String::new());
+        user.identities.github_id_v4.signature.publisher.name = PublisherAuthority::Ldap;
+        let res = post(
+            &mut app,
+            "/cis/api/change/v2/user",
+            &sign(user),
+            &nobody_soa(),
+        )
+        .await;
+        assert!(res.status().is_success());
+    }
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn duplicate_primary_email() -> Result<(), Error> {
+    reset()?;
+    let app = App::new().service(test_app().await);
+    let mut app = test::init_service(app).await;
+
+    let res = post(
+        &mut app,
+        "/cis/api/change/v2/user",
+        &signed_user(1, false),
+        &nobody_soa(),
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let mut user = signed_user(2, false);
+    user.primary_email.value = Some(String::from("hans1@knall.org"));
+    let res = post(
+        &mut app,
+        "/cis/api/change/v2/user",
+        &sign(user),
+        &nobody_soa(),
+    )
+    .await;
+    assert_eq!(res.status().as_u16(), 409);
+    assert_eq!(
+        read_json(res).await,
+        json!({ "error": "duplicate_value", "field": "primary_email" })
+    );
+
+    let user = signed_user(2, false);
+    let res = post(&mut app, "/cis/api/change/v2/user", &user, &nobody_soa()).await;
+    assert!(res.status().is_success());
+
+    let mut update = user.clone();
+    update.primary_email.value = Some(String::from("hans1@knall.org"));
+    update.primary_email.signature.publisher.name = PublisherAuthority::AccessProvider;
+    let res = post(
+        &mut app,
+        "/cis/api/change/v2/user",
+        &sign(update),
+        &nobody_soa(),
+    )
+    .await;
+    assert_eq!(res.status().as_u16(), 409);
+    assert_eq!(
+        read_json(res).await,
+        json!({ "error": "duplicate_value", "field": "primary_email" })
+    );
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn duplicate_primary_username_on_create() -> Result<(), Error> {
+    reset()?;
+    let app = App::new().service(test_app().await);
+    let mut app = test::init_service(app).await;
+
+    let res = post(
+        &mut app,
+        "/cis/api/change/v2/user",
+        &signed_user(1, false),
+        &nobody_soa(),
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let mut user = signed_user(2, false);
+    user.primary_username.value = Some(String::from("Hans1"));
+    let res = post(
+        &mut app,
+        "/cis/api/change/v2/user",
+        &sign(user),
+        &nobody_soa(),
+    )
+    .await;
+    assert_eq!(res.status().as_u16(), 409);
+    assert_eq!(
+        read_json(res).await,
+        json!({ "error": "duplicate_value", "field": "primary_username" })
+    );
+    Ok(())
+}
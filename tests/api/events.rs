@@ -0,0 +1,87 @@
+use crate::helpers::api::*;
+use crate::helpers::db::reset;
+use crate::helpers::misc::nobody_soa;
+use crate::helpers::misc::read_json;
+use crate::helpers::misc::staff_soa;
+use crate::helpers::misc::test_app;
+use crate::helpers::users::deletion_request;
+use crate::helpers::users::sign;
+use crate::helpers::users::signed_user;
+use crate::helpers::users::user_uuid;
+use actix_web::test;
+use actix_web::App;
+use cis_profile::schema::PublisherAuthority;
+use failure::Error;
+
+#[actix_rt::test]
+async fn page_through_events() -> Result<(), Error> {
+    reset()?;
+    let app = App::new().service(test_app().await);
+    let mut app = test::init_service(app).await;
+
+    for n in 1..=3 {
+        let res = post(
+            &mut app,
+            "/cis/api/change/v2/user",
+            &signed_user(n, false),
+            &nobody_soa(),
+        )
+        .await;
+        assert!(res.status().is_success());
+    }
+    let user = signed_user(1, false);
+    let mut update = user.clone();
+    update.last_name.value = Some(String::from("Knall"));
+    update.last_name.signature.publisher.name = PublisherAuthority::Mozilliansorg;
+    let res = post(
+        &mut app,
+        "/cis/api/change/v2/user",
+        &sign(update),
+        &nobody_soa(),
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let res = get(&mut app, "/cis/api/change/v2/events?limit=3", &nobody_soa()).await;
+    assert_eq!(res.status().as_u16(), 403);
+
+    let staff = staff_soa();
+    let res = get(&mut app, "/cis/api/change/v2/events?limit=3", &staff).await;
+    let page = read_json(res).await;
+    let events = page["events"].as_array().unwrap();
+    assert_eq!(events.len(), 3);
+    assert_eq!(events[0]["event"], "create");
+    assert_eq!(events[0]["uuid"], user_uuid(&user));
+
+    let res = get(
+        &mut app,
+        &format!("/cis/api/change/v2/events?after={}", page["next"]),
+        &staff,
+    )
+    .await;
+    let page = read_json(res).await;
+    let events = page["events"].as_array().unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0]["event"], "update");
+    assert_eq!(events[0]["version"], 2);
+    assert!(events[0]["fields"]
+        .as_array()
+        .unwrap()
+        .contains(&"last_name".into()));
+    assert_eq!(events[0]["publishers"][0], "mozilliansorg");
+
+    let endpoint = format!("/cis/api/change/v2/user/{}?mode=hard", user_uuid(&user));
+    let request = deletion_request(1, PublisherAuthority::Cis);
+    let res = delete_json(&mut app, &endpoint, &request, &nobody_soa()).await;
+    assert!(res.status().is_success());
+    let res = get(
+        &mut app,
+        &format!("/cis/api/change/v2/events?after={}", page["next"]),
+        &staff,
+    )
+    .await;
+    let page = read_json(res).await;
+    assert_eq!(page["events"][0]["event"], "erase");
+    assert_eq!(page["events"][0]["publishers"][0], "cis");
+    Ok(())
+}
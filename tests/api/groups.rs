@@ -0,0 +1,128 @@
+use crate::helpers::api::*;
+use crate::helpers::db::reset;
+use crate::helpers::misc::nobody_soa;
+use crate::helpers::misc::read_json;
+use crate::helpers::misc::staff_soa;
+use crate::helpers::misc::test_app;
+use crate::helpers::users::sign;
+use crate::helpers::users::signed_user;
+use crate::helpers::users::user_uuid;
+use actix_web::http::StatusCode;
+use actix_web::test;
+use actix_web::App;
+use cis_profile::schema::Display;
+use cis_profile::schema::Profile;
+use cis_profile::schema::PublisherAuthority;
+use failure::Error;
+use serde_json::json;
+use serde_json::Value;
+
+fn user_with_groups(n: u64, groups: Value, display: Display) -> Profile {
+    let mut p = signed_user(n, false);
+    p.access_information.mozilliansorg.values = serde_json::from_value(groups).unwrap();
+    p.access_information.mozilliansorg.metadata.display = Some(display);
+    p.access_information.mozilliansorg.signature.publisher.name = PublisherAuthority::Mozilliansorg;
+    sign(p)
+}
+
+#[actix_rt::test]
+async fn group_members_and_reverse_lookup() -> Result<(), Error> {
+    reset()?;
+    let app = App::new().service(test_app().await);
+    let mut app = test::init_service(app).await;
+
+    let users = vec![
+        user_with_groups(1, json!({ "dinos": null }), Display::Public),
+        user_with_groups(2, json!({ "dinos": null, "nda": null }), Display::Public),
+        user_with_groups(3, json!({ "dinos": null }), Display::Staff),
+    ];
+    for user in &users {
+        let res = post(&mut app, "/cis/api/change/v2/user", user, &nobody_soa()).await;
+        assert!(res.status().is_success());
+    }
+    let mut public_members = vec![user_uuid(&users[0]), user_uuid(&users[1])];
+    public_members.sort();
+
+    let res = get(
+        &mut app,
+        "/cis/api/person/v2/groups/mozilliansorg/dinos/members?limit=1",
+        &nobody_soa(),
+    )
+    .await;
+    let page = read_json(res).await;
+    assert_eq!(page["members"], json!([public_members[0]]));
+    let res = get(
+        &mut app,
+        &format!(
+            "/cis/api/person/v2/groups/mozilliansorg/dinos/members?after={}",
+            page["next"].as_str().unwrap()
+        ),
+        &nobody_soa(),
+    )
+    .await;
+    let page = read_json(res).await;
+    assert_eq!(page["members"], json!([public_members[1]]));
+
+    let staff = staff_soa();
+    let res = get(
+        &mut app,
+        "/cis/api/person/v2/groups/mozilliansorg/dinos/members",
+        &staff,
+    )
+    .await;
+    assert_eq!(read_json(res).await["members"].as_array().unwrap().len(), 3);
+
+    let res = get(
+        &mut app,
+        &format!(
+            "/cis/api/person/v2/user/uuid/{}/groups",
+            user_uuid(&users[1])
+        ),
+        &nobody_soa(),
+    )
+    .await;
+    assert_eq!(
+        read_json(res).await,
+        json!({ "mozilliansorg": ["dinos", "nda"] })
+    );
+    let res = get(
+        &mut app,
+        &format!(
+            "/cis/api/person/v2/user/uuid/{}/groups",
+            user_uuid(&users[2])
+        ),
+        &nobody_soa(),
+    )
+    .await;
+    assert_eq!(read_json(res).await, json!({}));
+
+    let update = user_with_groups(1, json!({ "t-rex": null }), Display::Public);
+    let res = post(&mut app, "/cis/api/change/v2/user", &update, &nobody_soa()).await;
+    assert!(res.status().is_success());
+    let res = get(
+        &mut app,
+        "/cis/api/person/v2/groups/mozilliansorg/dinos/members",
+        &staff,
+    )
+    .await;
+    assert_eq!(read_json(res).await["members"].as_array().unwrap().len(), 2);
+    let res = get(
+        &mut app,
+        "/cis/api/person/v2/groups/mozilliansorg/t-rex/members",
+        &staff,
+    )
+    .await;
+    assert_eq!(
+        read_json(res).await["members"],
+        json!([user_uuid(&users[0])])
+    );
+
+    let res = get(
+        &mut app,
+        "/cis/api/person/v2/user/uuid/00000000-0000-0000-0000-000000000000/groups",
+        &nobody_soa(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    Ok(())
+}
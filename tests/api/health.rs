@@ -20,3 +20,19 @@ async fn healthz() -> Result<(), Error> {
     assert!(res.status().is_success());
     Ok(())
 }
+
+#[actix_rt::test]
+async fn publisher_rules_status() -> Result<(), Error> {
+    let app = App::new().service(test_app().await);
+    let mut app = test::init_service(app).await;
+    let req = test::TestRequest::get()
+        .uri("/status/publisher_rules")
+        .to_request();
+    let res = test::call_service(&mut app, req).await;
+    assert!(res.status().is_success());
+    assert_eq!(
+        read_json(res).await,
+        json!({ "stale": false, "valid_till": null })
+    );
+    Ok(())
+}
@@ -1,2 +1,12 @@
 mod basic;
+mod change;
+mod delete;
+mod errors;
+mod events;
+mod groups;
 mod health;
+mod person;
+mod stream;
+mod users;
+mod webhooks;
+mod well_known;
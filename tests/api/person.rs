@@ -0,0 +1,224 @@
+use crate::helpers::api::*;
+use crate::helpers::db::reset;
+use crate::helpers::misc::nobody_soa;
+use crate::helpers::misc::read_json;
+use crate::helpers::misc::staff_soa;
+use crate::helpers::misc::test_app;
+use crate::helpers::users::sign;
+use crate::helpers::users::signed_user;
+use crate::helpers::users::user_email;
+use crate::helpers::users::user_uuid;
+use actix_web::test;
+use actix_web::App;
+use cis_profile::schema::Display;
+use cis_profile::schema::PublisherAuthority;
+use failure::Error;
+use uuid::Uuid;
+
+#[actix_rt::test]
+async fn retrieve_user() -> Result<(), Error> {
+    reset()?;
+    let app = App::new().service(test_app().await);
+    let mut app = test::init_service(app).await;
+
+    let user = signed_user(1, true);
+    let res = post(&mut app, "/cis/api/change/v2/user", &user, &nobody_soa()).await;
+    assert!(res.status().is_success());
+
+    for endpoint in &[
+        format!("/cis/api/person/v2/user/uuid/{}", user_uuid(&user)),
+        String::from("/cis/api/person/v2/user/user_id/fire1"),
+        format!(
+            "/cis/api/person/v2/user/primary_email/{}",
+            user_email(&user)
+        ),
+        String::from("/cis/api/person/v2/user/primary_username/Hans1"),
+        String::from("/cis/api/person/v2/user/user_id/fire1?active=true"),
+    ] {
+        let res = get(&mut app, endpoint, &nobody_soa()).await;
+        assert!(res.status().is_success());
+        assert_eq!(read_json(res).await["user_id"]["value"], "fire1");
+    }
+
+    let res = get(
+        &mut app,
+        "/cis/api/person/v2/user/user_id/fire1?active=false",
+        &nobody_soa(),
+    )
+    .await;
+    assert!(!res.status().is_success());
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn retrieve_user_scrubbed() -> Result<(), Error> {
+    reset()?;
+    let app = App::new().service(test_app().await);
+    let mut app = test::init_service(app).await;
+
+    let mut user = signed_user(1, true);
+    user.fun_title.value = Some(String::from("Dino"));
+    user.fun_title.metadata.display = Some(Display::Staff);
+    user.fun_title.signature.publisher.name = PublisherAuthority::Ldap;
+    let user = sign(user);
+    let res = post(&mut app, "/cis/api/change/v2/user", &user, &nobody_soa()).await;
+    assert!(res.status().is_success());
+
+    let res = get(
+        &mut app,
+        "/cis/api/person/v2/user/user_id/fire1",
+        &nobody_soa(),
+    )
+    .await;
+    let profile = read_json(res).await;
+    assert_eq!(profile["first_name"]["value"], "Hans1");
+    assert!(profile["fun_title"]["value"].is_null());
+
+    let staff = staff_soa();
+    let res = get(&mut app, "/cis/api/person/v2/user/user_id/fire1", &staff).await;
+    let profile = read_json(res).await;
+    assert_eq!(profile["fun_title"]["value"], "Dino");
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn retrieve_user_history() -> Result<(), Error> {
+    reset()?;
+    let app = App::new().service(test_app().await);
+    let mut app = test::init_service(app).await;
+
+    let user = signed_user(1, true);
+    let res = post(&mut app, "/cis/api/change/v2/user", &user, &nobody_soa()).await;
+    assert!(res.status().is_success());
+
+    let mut update = user.clone();
+    update.last_name.value = Some(String::from("Knall"));
+    update.last_name.signature.publisher.name = PublisherAuthority::Mozilliansorg;
+    let update = sign(update);
+    let res = post(&mut app, "/cis/api/change/v2/user", &update, &nobody_soa()).await;
+    assert!(res.status().is_success());
+    assert_eq!(read_json(res).await["version"], 2);
+
+    let history = format!("/cis/api/person/v2/user/uuid/{}/history", user_uuid(&user));
+    let res = get(&mut app, &history, &nobody_soa()).await;
+    let history_entries = read_json(res).await;
+    assert_eq!(history_entries[0]["version"], 1);
+    assert_eq!(history_entries[0]["replaced_by"][0], "mozilliansorg");
+
+    let res = get(&mut app, &format!("{}/1", history), &nobody_soa()).await;
+    assert_eq!(read_json(res).await["last_name"]["value"], "Knall1");
+
+    let unknown = format!("/cis/api/person/v2/user/uuid/{}/history", Uuid::nil());
+    let res = get(&mut app, &unknown, &nobody_soa()).await;
+    assert_eq!(res.status().as_u16(), 404);
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn retrieve_audit_log() -> Result<(), Error> {
+    reset()?;
+    let app = App::new().service(test_app().await);
+    let mut app = test::init_service(app).await;
+
+    let user = signed_user(1, true);
+    let res = post(&mut app, "/cis/api/change/v2/user", &user, &nobody_soa()).await;
+    assert!(res.status().is_success());
+
+    let mut update = user.clone();
+    update.last_name.value = Some(String::from("Knall"));
+    update.last_name.signature.publisher.name = PublisherAuthority::Mozilliansorg;
+    let update = sign(update);
+    let res = post(&mut app, "/cis/api/change/v2/user", &update, &nobody_soa()).await;
+    assert!(res.status().is_success());
+
+    let audit = format!(
+        "/cis/api/person/v2/audit?uuid={}&publisher=mozilliansorg",
+        user_uuid(&user)
+    );
+    let res = get(&mut app, &audit, &nobody_soa()).await;
+    assert_eq!(res.status().as_u16(), 403);
+
+    let staff = staff_soa();
+    let res = get(&mut app, &audit, &staff).await;
+    let changes = read_json(res).await;
+    assert_eq!(changes.as_array().map(|a| a.len()), Some(1));
+    assert_eq!(changes[0]["field"], "last_name");
+    assert_eq!(changes[0]["operation"], "update");
+    assert_eq!(changes[0]["old_value"], "Knall1");
+    assert_eq!(changes[0]["new_value"], "Knall");
+
+    let audit = format!(
+        "/cis/api/person/v2/audit?uuid={}&field=first_name",
+        user_uuid(&user)
+    );
+    let res = get(&mut app, &audit, &staff).await;
+    let changes = read_json(res).await;
+    assert_eq!(changes[0]["operation"], "create");
+    assert_eq!(changes[0]["publisher"], "ldap");
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn retrieve_user_by_identity() -> Result<(), Error> {
+    reset()?;
+    let app = App::new().service(test_app().await);
+    let mut app = test::init_service(app).await;
+
+    let mut user = signed_user(1, false);
+    user.identities.github_id_v3.value = Some(String::from("1234"));
+    user.identities.github_id_v3.metadata.display = Some(Display::Public);
+    user.identities.github_id_v3.signature.publisher.name = PublisherAuthority::Ldap;
+    user.identities.bugzilla_mozilla_org_primary_email.value =
+        Some(String::from("hans1@bugzilla.org"));
+    user.identities
+        .bugzilla_mozilla_org_primary_email
+        .metadata
+        .display = Some(Display::Staff);
+    user.identities
+        .bugzilla_mozilla_org_primary_email
+        .signature
+        .publisher
+        .name = PublisherAuthority::Ldap;
+    let user = sign(user);
+    let res = post(&mut app, "/cis/api/change/v2/user", &user, &nobody_soa()).await;
+    assert!(res.status().is_success());
+
+    let res = get(
+        &mut app,
+        "/cis/api/person/v2/user/identity/github_id_v3/1234",
+        &nobody_soa(),
+    )
+    .await;
+    let profile = read_json(res).await;
+    assert_eq!(profile["user_id"]["value"], "fire1");
+    assert!(profile["identities"]["bugzilla_mozilla_org_primary_email"]["value"].is_null());
+
+    let res = get(
+        &mut app,
+        "/cis/api/person/v2/user/identity/github_id_v3/1234?active=false",
+        &nobody_soa(),
+    )
+    .await;
+    assert_eq!(res.status().as_u16(), 404);
+
+    let bugzilla =
+        "/cis/api/person/v2/user/identity/bugzilla_mozilla_org_primary_email/hans1@bugzilla.org";
+    let res = get(&mut app, bugzilla, &nobody_soa()).await;
+    assert_eq!(res.status().as_u16(), 404);
+    let staff = staff_soa();
+    let res = get(&mut app, bugzilla, &staff).await;
+    let profile = read_json(res).await;
+    assert_eq!(
+        profile["identities"]["bugzilla_mozilla_org_primary_email"]["value"],
+        "hans1@bugzilla.org"
+    );
+
+    let res = get(
+        &mut app,
+        "/cis/api/person/v2/user/identity/dino_id/1234",
+        &nobody_soa(),
+    )
+    .await;
+    assert_eq!(res.status().as_u16(), 400);
+    Ok(())
+}
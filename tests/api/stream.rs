@@ -0,0 +1,69 @@
+use crate::helpers::api::*;
+use crate::helpers::db::reset;
+use crate::helpers::misc::nobody_soa;
+use crate::helpers::misc::read_json;
+use crate::helpers::misc::staff_soa;
+use crate::helpers::misc::test_app;
+use crate::helpers::users::signed_user;
+use crate::helpers::users::user_uuid;
+use actix_web::test;
+use actix_web::web;
+use actix_web::App;
+use dino_park_cis::notify::listen;
+use dino_park_cis::notify::Broadcaster;
+use failure::Error;
+use futures::StreamExt;
+use std::env;
+use std::time::Duration;
+
+#[actix_rt::test]
+async fn stream_profile_changes() -> Result<(), Error> {
+    reset()?;
+    let broadcaster = web::Data::new(Broadcaster::default());
+    actix_rt::spawn(listen(env::var("DPP_PG_URL")?, broadcaster.clone()));
+    for _ in 0..500 {
+        if broadcaster.is_listening() {
+            break;
+        }
+        actix_rt::time::delay_for(Duration::from_millis(10)).await;
+    }
+    assert!(
+        broadcaster.is_listening(),
+        "listener did not connect within 5s"
+    );
+    let app = App::new()
+        .app_data(broadcaster.clone())
+        .service(test_app().await);
+    let mut app = test::init_service(app).await;
+
+    let res = get(&mut app, "/status/listener", &nobody_soa()).await;
+    assert_eq!(read_json(res).await["listening"], true);
+
+    let user = signed_user(2, false);
+    let endpoint = format!("/cis/api/change/v2/stream?uuid={}", user_uuid(&user));
+    let res = get(&mut app, &endpoint, &nobody_soa()).await;
+    assert_eq!(res.status().as_u16(), 403);
+
+    let staff = staff_soa();
+    let mut res = get(&mut app, &endpoint, &staff).await;
+    assert!(res.status().is_success());
+    let mut body = res.take_body();
+
+    for n in 1..=2 {
+        let res = post(
+            &mut app,
+            "/cis/api/change/v2/user",
+            &signed_user(n, false),
+            &nobody_soa(),
+        )
+        .await;
+        assert!(res.status().is_success());
+    }
+
+    let message = body.next().await.unwrap().unwrap();
+    let message = String::from_utf8(message.to_vec())?;
+    assert!(message.contains("event: profile_changed\n"));
+    assert!(message.contains(&user_uuid(&user)));
+    assert!(!message.contains(&user_uuid(&signed_user(1, false))));
+    Ok(())
+}
@@ -0,0 +1,158 @@
+use crate::helpers::api::*;
+use crate::helpers::db::reset;
+use crate::helpers::misc::nobody_soa;
+use crate::helpers::misc::read_json;
+use crate::helpers::misc::staff_soa;
+use crate::helpers::misc::test_app;
+use crate::helpers::users::signed_user;
+use crate::helpers::users::user_uuid;
+use actix_web::test;
+use actix_web::App;
+use chrono::Duration;
+use chrono::Utc;
+use failure::Error;
+use serde_json::Value;
+
+#[actix_rt::test]
+async fn page_through_users() -> Result<(), Error> {
+    reset()?;
+    let app = App::new().service(test_app().await);
+    let mut app = test::init_service(app).await;
+
+    let since = (Utc::now() - Duration::minutes(1)).naive_utc();
+    let mut uuids = vec![];
+    for n in 1..=3 {
+        let user = signed_user(n, n == 3);
+        let res = post(&mut app, "/cis/api/change/v2/user", &user, &nobody_soa()).await;
+        assert!(res.status().is_success());
+        uuids.push(user_uuid(&user));
+    }
+    uuids.sort();
+
+    let res = get(&mut app, "/cis/api/person/v2/users?limit=2", &nobody_soa()).await;
+    let page = read_json(res).await;
+    let users = page["users"].as_array().unwrap();
+    assert_eq!(users.len(), 2);
+    assert_eq!(users[0]["uuid"]["value"], uuids[0]);
+    assert!(users[0]["last_modified"]["value"].is_null());
+    let res = get(
+        &mut app,
+        &format!(
+            "/cis/api/person/v2/users?after={}",
+            page["next"].as_str().unwrap()
+        ),
+        &nobody_soa(),
+    )
+    .await;
+    let page = read_json(res).await;
+    assert_eq!(page["users"].as_array().unwrap().len(), 1);
+    assert_eq!(page["users"][0]["uuid"]["value"], uuids[2]);
+
+    let res = get(
+        &mut app,
+        "/cis/api/person/v2/users?trust=staff",
+        &nobody_soa(),
+    )
+    .await;
+    assert_eq!(res.status().as_u16(), 403);
+    let res = get(
+        &mut app,
+        "/cis/api/person/v2/users/ndjson?trust=staff",
+        &nobody_soa(),
+    )
+    .await;
+    assert_eq!(res.status().as_u16(), 403);
+
+    let staff = staff_soa();
+    let res = get(&mut app, "/cis/api/person/v2/users?trust=staff", &staff).await;
+    let page = read_json(res).await;
+    assert_eq!(page["users"].as_array().unwrap().len(), 1);
+    assert_eq!(page["users"][0]["user_id"]["value"], "fire3");
+
+    let res = get(
+        &mut app,
+        "/cis/api/person/v2/users?active=false",
+        &nobody_soa(),
+    )
+    .await;
+    assert_eq!(read_json(res).await["users"].as_array().unwrap().len(), 0);
+
+    let res = get(
+        &mut app,
+        &format!(
+            "/cis/api/person/v2/users?modified_since={}",
+            since.format("%Y-%m-%dT%H:%M:%S")
+        ),
+        &staff,
+    )
+    .await;
+    let page = read_json(res).await;
+    assert_eq!(page["users"].as_array().unwrap().len(), 3);
+    assert!(page["users"][0]["last_modified"]["value"].is_string());
+    let until = since + Duration::days(1);
+    let res = get(
+        &mut app,
+        &format!(
+            "/cis/api/person/v2/users?modified_since={}",
+            until.format("%Y-%m-%dT%H:%M:%S")
+        ),
+        &staff,
+    )
+    .await;
+    assert_eq!(read_json(res).await["users"].as_array().unwrap().len(), 0);
+
+    let res = get(
+        &mut app,
+        "/cis/api/person/v2/users?trust=dino",
+        &nobody_soa(),
+    )
+    .await;
+    assert_eq!(res.status().as_u16(), 400);
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn stream_users_as_ndjson() -> Result<(), Error> {
+    reset()?;
+    let app = App::new().service(test_app().await);
+    let mut app = test::init_service(app).await;
+
+    for n in 1..=3 {
+        let res = post(
+            &mut app,
+            "/cis/api/change/v2/user",
+            &signed_user(n, false),
+            &nobody_soa(),
+        )
+        .await;
+        assert!(res.status().is_success());
+    }
+
+    let res = get(
+        &mut app,
+        "/cis/api/person/v2/users/ndjson?limit=2",
+        &nobody_soa(),
+    )
+    .await;
+    assert_eq!(
+        res.headers().get("content-type").unwrap(),
+        "application/x-ndjson"
+    );
+    let body = test::read_body(res).await;
+    let users = body
+        .split(|b| *b == b'\n')
+        .filter(|line| !line.is_empty())
+        .map(|line| serde_json::from_slice(line))
+        .collect::<Result<Vec<Value>, _>>()?;
+    assert_eq!(users.len(), 3);
+    assert!(users.iter().all(|u| u["last_modified"]["value"].is_null()));
+
+    let res = get(
+        &mut app,
+        "/cis/api/person/v2/users/ndjson?trust=dino",
+        &nobody_soa(),
+    )
+    .await;
+    assert_eq!(res.status().as_u16(), 400);
+    Ok(())
+}
@@ -0,0 +1,211 @@
+use crate::helpers::api::*;
+use crate::helpers::db::get_pool;
+use crate::helpers::db::reset;
+use crate::helpers::misc::nobody_soa;
+use crate::helpers::misc::read_json;
+use crate::helpers::misc::test_app;
+use crate::helpers::misc::Soa;
+use crate::helpers::users::signed_user;
+use crate::helpers::users::user_uuid;
+use actix_web::http::StatusCode;
+use actix_web::test;
+use actix_web::web;
+use actix_web::App;
+use actix_web::HttpRequest;
+use actix_web::HttpResponse;
+use diesel::RunQueryDsl;
+use dino_park_cis::db::webhooks::claim_due;
+use dino_park_cis::db::webhooks::fan_out;
+use dino_park_cis::db::webhooks::record_attempt;
+use dino_park_cis::settings::WebhookSettings;
+use dino_park_cis::webhooks::client;
+use dino_park_cis::webhooks::run_once;
+use dino_park_cis::webhooks::signature;
+use dino_park_cis::webhooks::SIGNATURE_HEADER;
+use dino_park_trust::AALevel;
+use dino_park_trust::GroupsTrust;
+use dino_park_trust::Trust;
+use failure::Error;
+use serde_json::json;
+use serde_json::Value;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+
+#[derive(Default)]
+struct Receiver {
+    fail: AtomicBool,
+    requests: Mutex<Vec<(String, Vec<u8>)>>,
+}
+
+async fn hook(receiver: web::Data<Receiver>, req: HttpRequest, body: web::Bytes) -> HttpResponse {
+    let signature = req
+        .headers()
+        .get(SIGNATURE_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_owned();
+    receiver
+        .requests
+        .lock()
+        .unwrap()
+        .push((signature, body.to_vec()));
+    if receiver.fail.load(Ordering::SeqCst) {
+        HttpResponse::InternalServerError().finish()
+    } else {
+        HttpResponse::Ok().finish()
+    }
+}
+
+fn admin_soa() -> Soa {
+    Soa::new("admin", Trust::Staff, GroupsTrust::Admin, AALevel::Low)
+}
+
+fn settings() -> WebhookSettings {
+    WebhookSettings {
+        max_attempts: 2,
+        backoff_seconds: 0,
+        poll_interval_ms: 0,
+    }
+}
+
+#[actix_rt::test]
+async fn deliver_signed_events() -> Result<(), Error> {
+    reset()?;
+    let receiver = web::Data::new(Receiver::default());
+    let r = receiver.clone();
+    let srv = test::start(move || {
+        App::new()
+            .app_data(r.clone())
+            .route("/hook", web::post().to(hook))
+    });
+    let app = App::new().service(test_app().await);
+    let mut app = test::init_service(app).await;
+
+    let webhook = json!({ "url": srv.url("/hook"), "secret": "s3cret", "field": "first_name" });
+    let res = post(&mut app, "/cis/api/webhooks/v2", &webhook, &nobody_soa()).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    let res = post(&mut app, "/cis/api/webhooks/v2", &webhook, &admin_soa()).await;
+    assert!(res.status().is_success());
+    let webhook = read_json(res).await;
+    assert!(webhook["secret"].is_null());
+
+    let user = signed_user(1, false);
+    let res = post(&mut app, "/cis/api/change/v2/user", &user, &nobody_soa()).await;
+    assert!(res.status().is_success());
+
+    assert_eq!(run_once(&get_pool(), &client()?, &settings()).await?, 1);
+    let requests = receiver.requests.lock().unwrap().clone();
+    assert_eq!(requests.len(), 1);
+    let (sig, body) = &requests[0];
+    assert_eq!(sig, &format!("sha256={}", signature("s3cret", body)?));
+    let event: Value = serde_json::from_slice(body)?;
+    assert_eq!(event["uuid"], user_uuid(&user));
+    assert_eq!(event["event"], "create");
+
+    let res = get(
+        &mut app,
+        &format!("/cis/api/webhooks/v2/{}/deliveries", webhook["id"]),
+        &admin_soa(),
+    )
+    .await;
+    let deliveries = read_json(res).await;
+    assert_eq!(deliveries[0]["status"], "delivered");
+    assert_eq!(deliveries[0]["attempts"], 1);
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn dead_letter_and_replay() -> Result<(), Error> {
+    reset()?;
+    let receiver = web::Data::new(Receiver::default());
+    receiver.fail.store(true, Ordering::SeqCst);
+    let r = receiver.clone();
+    let srv = test::start(move || {
+        App::new()
+            .app_data(r.clone())
+            .route("/hook", web::post().to(hook))
+    });
+    let app = App::new().service(test_app().await);
+    let mut app = test::init_service(app).await;
+
+    let webhook = json!({ "url": srv.url("/hook"), "secret": "s3cret" });
+    let res = post(&mut app, "/cis/api/webhooks/v2", &webhook, &admin_soa()).await;
+    let id = read_json(res).await["id"].clone();
+
+    let res = post(
+        &mut app,
+        "/cis/api/change/v2/user",
+        &signed_user(1, false),
+        &nobody_soa(),
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let pool = get_pool();
+    assert_eq!(run_once(&pool, &client()?, &settings()).await?, 1);
+    assert_eq!(run_once(&pool, &client()?, &settings()).await?, 1);
+    assert_eq!(run_once(&pool, &client()?, &settings()).await?, 0);
+    assert_eq!(receiver.requests.lock().unwrap().len(), 2);
+
+    let res = get(
+        &mut app,
+        &format!("/cis/api/webhooks/v2/{}/deliveries?status=dead", id),
+        &admin_soa(),
+    )
+    .await;
+    let deliveries = read_json(res).await;
+    assert_eq!(deliveries[0]["attempts"], 2);
+    assert!(deliveries[0]["last_error"].is_string());
+
+    receiver.fail.store(false, Ordering::SeqCst);
+    let res = post(
+        &mut app,
+        &format!("/cis/api/webhooks/v2/{}/replay", id),
+        &json!({}),
+        &admin_soa(),
+    )
+    .await;
+    assert_eq!(read_json(res).await["replayed"], 1);
+    assert_eq!(run_once(&pool, &client()?, &settings()).await?, 1);
+
+    let res = get(
+        &mut app,
+        &format!("/cis/api/webhooks/v2/{}/deliveries", id),
+        &admin_soa(),
+    )
+    .await;
+    assert_eq!(read_json(res).await[0]["status"], "delivered");
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn expired_lease_is_not_recorded() -> Result<(), Error> {
+    reset()?;
+    let app = App::new().service(test_app().await);
+    let mut app = test::init_service(app).await;
+
+    let webhook = json!({ "url": "http://127.0.0.1:1/hook", "secret": "s3cret" });
+    let res = post(&mut app, "/cis/api/webhooks/v2", &webhook, &admin_soa()).await;
+    assert!(res.status().is_success());
+    let res = post(
+        &mut app,
+        "/cis/api/change/v2/user",
+        &signed_user(1, false),
+        &nobody_soa(),
+    )
+    .await;
+    assert!(res.status().is_success());
+
+    let connection = get_pool().get()?;
+    fan_out(&connection)?;
+    let (stale, _, _) = claim_due(&connection, 10)?.remove(0);
+    diesel::sql_query(
+        "UPDATE webhook_deliveries SET next_attempt_at = NOW() - INTERVAL '1 second'",
+    )
+    .execute(&connection)?;
+    let (current, _, _) = claim_due(&connection, 10)?.remove(0);
+    assert!(!record_attempt(&connection, &stale, None, &settings())?);
+    assert!(record_attempt(&connection, &current, None, &settings())?);
+    Ok(())
+}
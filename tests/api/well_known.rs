@@ -0,0 +1,43 @@
+use crate::helpers::misc::read_json;
+use crate::helpers::misc::test_app;
+use actix_web::http::header::CACHE_CONTROL;
+use actix_web::test;
+use actix_web::App;
+use failure::Error;
+use serde_json::json;
+
+#[actix_rt::test]
+async fn publisher_rules() -> Result<(), Error> {
+    let app = App::new().service(test_app().await);
+    let mut app = test::init_service(app).await;
+    let req = test::TestRequest::get()
+        .uri("/.well-known/mozilla-iam-publisher-rules")
+        .to_request();
+    let res = test::call_service(&mut app, req).await;
+    assert!(res.status().is_success());
+    let cache_control = res.headers().get(CACHE_CONTROL).unwrap().to_str()?;
+    assert!(cache_control.starts_with("max-age="));
+    let rules = read_json(res).await;
+    let expected: serde_json::Value = serde_json::from_str(include_str!("../data/rules.json"))?;
+    assert_eq!(
+        rules["create"]["last_name"],
+        expected["create"]["last_name"]
+    );
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn publisher_jwks() -> Result<(), Error> {
+    let app = App::new().service(test_app().await);
+    let mut app = test::init_service(app).await;
+    let req = test::TestRequest::get()
+        .uri("/.well-known/mozilla-iam-publisher-jwks")
+        .to_request();
+    let res = test::call_service(&mut app, req).await;
+    assert!(res.status().is_success());
+    let jwks = read_json(res).await;
+    assert_eq!(jwks["keys"][0]["kid"], json!("ldap"));
+    assert_eq!(jwks["keys"][0]["kty"], json!("RSA"));
+    assert_eq!(jwks["keys"][0]["e"], json!("AQAB"));
+    Ok(())
+}
@@ -0,0 +1,137 @@
+use crate::helpers::db::get_pool;
+use crate::helpers::db::reset;
+use crate::helpers::misc::test_rules;
+use crate::helpers::misc::test_store;
+use crate::helpers::users::basic_user;
+use crate::helpers::users::sign;
+use crate::helpers::users::signed_user;
+use crate::helpers::users::user_uuid;
+use cis_profile::schema::Profile;
+use cis_profile::schema::PublisherAuthority;
+use diesel::Connection;
+use dino_park_cis::db::change::store_profile;
+use dino_park_cis::db::retrieve::retrieve_history;
+use dino_park_cis::db::retrieve::retrieve_profile_entry_by_user_id;
+use dino_park_cis::error::DBError;
+use dino_park_cis::profile::change::change_profile;
+use dino_park_cis::profile::cis_attributes::IdentifierGenerator;
+use failure::Error;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use uuid::Uuid;
+
+fn is_concurrent_modification(e: &Error) -> bool {
+    e.downcast_ref::<DBError>() == Some(&DBError::ConcurrentModification)
+}
+
+#[test]
+fn versions_increase() -> Result<(), Error> {
+    reset()?;
+    let connection = get_pool().get()?;
+    let mut user = basic_user(1, false);
+    let mut version = 0;
+    for i in 0..300 {
+        user.fun_title.value = Some(i.to_string());
+        version = store_profile(&connection, user.clone(), version, &[])?.version;
+    }
+    assert_eq!(version, 300);
+    Ok(())
+}
+
+#[test]
+fn stale_writer() -> Result<(), Error> {
+    reset()?;
+    let connection = get_pool().get()?;
+    let user = basic_user(1, false);
+    assert_eq!(store_profile(&connection, user.clone(), 0, &[])?.version, 1);
+    assert_eq!(store_profile(&connection, user.clone(), 1, &[])?.version, 2);
+    let stale = store_profile(&connection, user.clone(), 1, &[]);
+    assert!(is_concurrent_modification(&stale.unwrap_err()));
+    let stale = store_profile(&connection, user, 0, &[]);
+    assert!(is_concurrent_modification(&stale.unwrap_err()));
+    Ok(())
+}
+
+#[test]
+fn concurrent_writers() -> Result<(), Error> {
+    reset()?;
+    let pool = get_pool();
+    let user = basic_user(1, false);
+    store_profile(&pool.get()?, user.clone(), 0, &[])?;
+
+    let writers = (0..8)
+        .map(|n| {
+            let pool = pool.clone();
+            let mut user = user.clone();
+            user.fun_title.value = Some(n.to_string());
+            thread::spawn(move || -> Result<(), Error> {
+                let connection = pool.get()?;
+                loop {
+                    let version = retrieve_profile_entry_by_user_id(&connection, "fire1")?
+                        .map(|pe| pe.version)
+                        .unwrap_or_default();
+                    match store_profile(&connection, user.clone(), version, &[]) {
+                        Ok(_) => return Ok(()),
+                        Err(e) if is_concurrent_modification(&e) => continue,
+                        Err(e) => return Err(e),
+                    }
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for writer in writers {
+        writer.join().unwrap()?;
+    }
+
+    let connection = pool.get()?;
+    let pe = retrieve_profile_entry_by_user_id(&connection, "fire1")?.unwrap();
+    assert_eq!(pe.version, 9);
+    let history = retrieve_history(&connection, Uuid::parse_str(&user_uuid(&user))?)?;
+    assert_eq!(history.len(), 8);
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn change_profile_retries() -> Result<(), Error> {
+    reset()?;
+    let pool = get_pool();
+    let store = test_store();
+    let rules = test_rules();
+    let ids = IdentifierGenerator::new("test");
+    let user = signed_user(1, false);
+    change_profile(&pool, &store, &rules, &ids, user.clone(), false).await?;
+
+    // Stores version 2 but keeps it uncommitted until the update below has
+    // read version 1 and waits for the row lock.
+    let (stored, wait) = mpsc::channel();
+    let writer = {
+        let pool = pool.clone();
+        thread::spawn(move || -> Result<(), Error> {
+            let connection = pool.get()?;
+            connection.transaction::<_, Error, _>(|| {
+                let pe = retrieve_profile_entry_by_user_id(&connection, "fire1")?.unwrap();
+                let mut p: Profile = serde_json::from_value(pe.profile)?;
+                p.fun_title.value = Some(String::from("Dino"));
+                store_profile(&connection, p, pe.version, &[])?;
+                stored.send(()).unwrap();
+                thread::sleep(Duration::from_millis(500));
+                Ok(())
+            })
+        })
+    };
+    wait.recv()?;
+
+    let mut update = user.clone();
+    update.last_name.value = Some(String::from("Knall"));
+    update.last_name.signature.publisher.name = PublisherAuthority::Mozilliansorg;
+    let status = change_profile(&pool, &store, &rules, &ids, sign(update), false).await?;
+    writer.join().unwrap()?;
+    assert_eq!(status.version, 3);
+
+    let pe = retrieve_profile_entry_by_user_id(&pool.get()?, "fire1")?.unwrap();
+    let p: Profile = serde_json::from_value(pe.profile)?;
+    assert_eq!(p.fun_title.value.as_deref(), Some("Dino"));
+    assert_eq!(p.last_name.value.as_deref(), Some("Knall"));
+    Ok(())
+}
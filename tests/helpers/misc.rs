@@ -8,7 +8,12 @@ use actix_web::HttpMessage;
 use base64::decode;
 use base64::encode;
 use cis_client::AsyncCisClientTrait;
+use cis_profile::crypto::SecretStore;
 use cis_profile::schema::Profile;
+use dino_park_cis::keys::jwks_from_pems;
+use dino_park_cis::keys::JwkSet;
+use dino_park_cis::profile::cis_attributes::IdentifierGenerator;
+use dino_park_cis::profile::publishers::PublisherRulesStore;
 use dino_park_gate::scope::ScopeAndUser;
 use dino_park_trust::AALevel;
 use dino_park_trust::GroupsTrust;
@@ -97,6 +102,10 @@ pub fn nobody_soa() -> Soa {
     Soa::new("nobody", Trust::Public, GroupsTrust::None, AALevel::Unknown)
 }
 
+pub fn staff_soa() -> Soa {
+    Soa::new("staff", Trust::Staff, GroupsTrust::None, AALevel::Low)
+}
+
 fn scope_from_sau_str(sau: &str) -> ScopeAndUser {
     let j = decode(sau).unwrap();
     serde_json::from_slice::<Soa>(&j).unwrap().into()
@@ -106,18 +115,86 @@ fn scope_from_headers(headers: &HeaderMap) -> ScopeAndUser {
     scope_from_sau_str(headers.get("sau").map(|v| v.to_str().unwrap()).unwrap())
 }
 
+/// Stands in for the scope middleware: takes the `ScopeAndUser` from the
+/// `sau` header.
+fn insert_scope_and_user<S>(req: ServiceRequest, srv: &mut S) -> S::Future
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse, Error = actix_web::Error>,
+{
+    if req.headers().contains_key("sau") {
+        let scope_and_user = scope_from_headers(req.headers());
+        req.extensions_mut().insert(scope_and_user);
+    }
+    srv.call(req)
+}
+
 pub async fn read_json<B: MessageBody>(res: ServiceResponse<B>) -> Value {
     serde_json::from_slice(test::read_body(res).await.as_ref()).unwrap()
 }
 
+pub fn test_store() -> SecretStore {
+    let sign_key = include_str!("../data/fake_key.json");
+    let verify_key = include_str!("../data/fake_key_public.pem");
+    let publishers = ["mozilliansorg", "hris", "ldap", "cis", "access_provider"];
+    SecretStore::default()
+        .with_sign_keys_from_inline_iter(
+            publishers
+                .iter()
+                .map(|p| (String::from(*p), String::from(sign_key))),
+        )
+        .unwrap()
+        .with_verify_keys_from_inline_iter(
+            publishers
+                .iter()
+                .map(|p| (String::from(*p), String::from(verify_key))),
+        )
+        .unwrap()
+}
+
+pub fn test_rules() -> PublisherRulesStore {
+    PublisherRulesStore::Static(serde_json::from_str(include_str!("../data/rules.json")).unwrap())
+}
+
+pub fn test_jwks() -> JwkSet {
+    jwks_from_pems(&[(
+        String::from("ldap"),
+        String::from(include_str!("../data/fake_key_public.pem")),
+    )])
+}
+
 pub async fn test_app() -> impl HttpServiceFactory {
     let pool = get_pool();
+    let secret_store = web::Data::new(test_store());
+    let rules = web::Data::new(test_rules());
+    let jwks = web::Data::new(test_jwks());
+    let ids = web::Data::new(IdentifierGenerator::new("test"));
     web::scope("")
         .data(pool.clone())
+        .app_data(secret_store)
+        .app_data(rules)
+        .app_data(jwks)
+        .app_data(ids)
         .service(healthz::healthz_app())
+        .service(status::status_app())
+        .service(well_known::well_known_app())
         .service(
             web::scope("/cis/api")
-                .wrap_fn(|req, srv| srv.call(req))
-                .service(api::change::change_app()),
+                .service(
+                    web::scope("/change/v2/events")
+                        .wrap_fn(insert_scope_and_user)
+                        .service(api::events::events_app()),
+                )
+                .service(
+                    web::scope("/change/v2/stream")
+                        .wrap_fn(insert_scope_and_user)
+                        .service(api::events::stream_app()),
+                )
+                .service(api::change::change_app())
+                .service(
+                    web::scope("")
+                        .wrap_fn(insert_scope_and_user)
+                        .service(api::person::person_app())
+                        .service(api::webhooks::webhooks_app()),
+                ),
         )
 }
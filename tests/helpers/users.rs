@@ -1,5 +1,8 @@
+use crate::helpers::misc::test_store;
 use cis_profile::schema::Display;
 use cis_profile::schema::Profile;
+use cis_profile::schema::PublisherAuthority;
+use cis_profile::utils::sign_full_profile;
 use uuid::Uuid;
 
 pub fn basic_user(n: u64, staff: bool) -> Profile {
@@ -33,3 +36,30 @@ pub fn user_uuid(p: &Profile) -> String {
 pub fn user_email(p: &Profile) -> String {
     p.primary_email.value.clone().unwrap()
 }
+
+pub fn signed_user(n: u64, staff: bool) -> Profile {
+    let mut p = basic_user(n, staff);
+    p.uuid.signature.publisher.name = PublisherAuthority::Cis;
+    p.primary_username.signature.publisher.name = PublisherAuthority::Cis;
+    p.user_id.signature.publisher.name = PublisherAuthority::Ldap;
+    p.active.signature.publisher.name = PublisherAuthority::Ldap;
+    p.first_name.signature.publisher.name = PublisherAuthority::Ldap;
+    p.last_name.signature.publisher.name = PublisherAuthority::Ldap;
+    p.primary_email.signature.publisher.name = PublisherAuthority::Ldap;
+    p.staff_information.staff.signature.publisher.name = PublisherAuthority::Ldap;
+    sign(p)
+}
+
+pub fn deletion_request(n: u64, publisher: PublisherAuthority) -> Profile {
+    let mut p = Profile::default();
+    p.user_id.value = Some(format!("fire{}", n));
+    p.user_id.signature.publisher.name = PublisherAuthority::Ldap;
+    p.active.value = Some(false);
+    p.active.signature.publisher.name = publisher;
+    sign(p)
+}
+
+pub fn sign(mut p: Profile) -> Profile {
+    sign_full_profile(&mut p, &test_store()).unwrap();
+    p
+}